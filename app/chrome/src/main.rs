@@ -1,9 +1,11 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
 use dfiles::aspects;
 use dfiles::containermanager::ContainerManager;
+use dfiles::dockerfile::Instruction;
 
 #[derive(Clone)]
 struct Chrome {}
@@ -16,37 +18,48 @@ impl aspects::ContainerAspect for Chrome {
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
         vec![
             aspects::DockerfileSnippet {
-                order: 91,
-                content: r#"
-ADD https://dl.google.com/linux/direct/google-talkplugin_current_amd64.deb /src/google-talkplugin_current_amd64.deb
-RUN apt-get update && apt-get install -y --no-install-recommends \
-        libpango1.0-0 \
-        libcanberra-gtk* \
-        hicolor-icon-theme \
-        libgl1-mesa-dri \
-        libgl1-mesa-glx \
-        libv4l-0 \
-        openjdk-11-jre \
-        fonts-symbola \
-    && curl -sSL https://dl.google.com/linux/linux_signing_key.pub | apt-key add - \
-    && echo "deb [arch=amd64] https://dl.google.com/linux/chrome/deb/ stable main" > /etc/apt/sources.list.d/google.list \
-    && apt-get update && apt-get install -y --no-install-recommends \
-        google-chrome-stable \
-    && dpkg -i /src/google-talkplugin_current_amd64.deb \
-    && apt-get purge --auto-remove -y curl \
-    && rm -rf /var/lib/apt/lists/* \
-    && rm -rf /src/*.deb"#.to_string(),
-            },
-            aspects::DockerfileSnippet {
-                order: 75,
+                phase: aspects::Phase::AppInstall,
                 content: String::from(
                     r#"COPY /etc/fonts/local.conf /etc/fonts/local.conf
 RUN chmod 655 /etc/fonts
 RUN chmod 644 /etc/fonts/local.conf"#,
                 ),
+                ..Default::default()
+            },
+            aspects::DockerfileSnippet {
+                phase: aspects::Phase::AppInstall,
+                instructions: vec![
+                    Instruction::Add {
+                        src: String::from(
+                            "https://dl.google.com/linux/direct/google-talkplugin_current_amd64.deb",
+                        ),
+                        dest: String::from("/src/google-talkplugin_current_amd64.deb"),
+                    },
+                    Instruction::Run(vec![
+                        String::from("dpkg -i /src/google-talkplugin_current_amd64.deb"),
+                        String::from("rm -rf /src/*.deb"),
+                    ]),
+                ],
+                ..Default::default()
             },
         ]
     }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::new(
+            aspects::Phase::AppInstall,
+            &[
+                "libpango1.0-0",
+                "libcanberra-gtk*",
+                "hicolor-icon-theme",
+                "libgl1-mesa-dri",
+                "libgl1-mesa-glx",
+                "libv4l-0",
+                "openjdk-11-jre",
+                "fonts-symbola",
+                "google-chrome-stable",
+            ],
+        )]
+    }
     fn container_files(&self) -> Vec<aspects::ContainerFile> {
         vec![aspects::ContainerFile {
             container_path: String::from("./etc/fonts/local.conf"),
@@ -90,7 +103,7 @@ RUN chmod 644 /etc/fonts/local.conf"#,
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let data_dir = String::from("/data");
     let home = env::var("HOME").expect("HOME must be set");
     let home_dir = format!("{}/.config", home);
@@ -102,6 +115,21 @@ fn main() -> Result<()> {
         vec![home_dir, data_dir.clone()],
         vec![
             Box::new(Chrome {}),
+            Box::new(aspects::AptRepository {
+                name: String::from("google-chrome"),
+                url: String::from("https://dl.google.com/linux/chrome/deb/"),
+                key: aspects::AptKey::Url(String::from(
+                    "https://dl.google.com/linux/linux_signing_key.pub",
+                )),
+                // Google, Inc. Linux Package Signing Key, see
+                // https://www.google.com/linuxrepositories/
+                fingerprint: Some(String::from(
+                    "EB4C 1BFD 4F04 2F6D DDCC  EC91 7721 F63B D38B 4796",
+                )),
+                suite: String::from("stable"),
+                components: vec![String::from("main")],
+                architecture: Some(String::from("amd64")),
+            }),
             Box::new(aspects::Name("chrome".to_string())),
             Box::new(aspects::CurrentUser::detect().context("detecting current user")?),
             Box::new(aspects::PulseAudio {}),
@@ -118,5 +146,7 @@ fn main() -> Result<()> {
         Some(String::from("bullseye")),
     )?;
 
-    mgr.execute().context("executing chrome in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing chrome in container")?;
+    Ok(ExitCode::from(code as u8))
 }
@@ -1,9 +1,11 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
 use dfiles::aspects;
 use dfiles::containermanager::ContainerManager;
+use dfiles::dockerfile::Instruction;
 
 #[derive(Clone)]
 struct Discord {}
@@ -14,35 +16,35 @@ impl aspects::ContainerAspect for Discord {
     }
 
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
-        vec![
-            aspects::DockerfileSnippet {
-                order: 91,
-                content: r#"WORKDIR /opt/
-RUN curl https://dl.discordapp.net/apps/linux/0.0.64/discord-0.0.64.deb > /opt/discord.deb && \
-    dpkg --force-depends -i /opt/discord.deb  ; rm /opt/discord.deb
-RUN apt-get update && apt-get --fix-broken install -y \
+        vec![aspects::DockerfileSnippet {
+            phase: aspects::Phase::AppInstall,
+            instructions: vec![
+                Instruction::Workdir(String::from("/opt/")),
+                Instruction::Add {
+                    src: String::from(
+                        "https://dl.discordapp.net/apps/linux/0.0.64/discord-0.0.64.deb",
+                    ),
+                    dest: String::from("/opt/discord.deb"),
+                },
+                Instruction::run("dpkg --force-depends -i /opt/discord.deb  ; rm /opt/discord.deb"),
+            ],
+            content: r#"RUN apt-get update && apt-get --fix-broken install -y \
   && apt-get purge --autoremove \
   && rm -rf /var/lib/apt/lists/* \
   && rm -rf /src/*.deb "#
-                    .to_string(),
-            },
-            aspects::DockerfileSnippet {
-                order: 92,
-                content: String::from(
-                    r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    libxshmfence1 \
-    libgbm1 \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
-                ),
-            },
-        ]
+                .to_string(),
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::new(
+            aspects::Phase::AppInstall,
+            &["libxshmfence1", "libgbm1"],
+        )]
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
     let container_path = format!("{}/.config/discord/", home);
 
@@ -67,5 +69,7 @@ fn main() -> Result<()> {
         None,
     )?;
 
-    mgr.execute().context("executing discord in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing discord in container")?;
+    Ok(ExitCode::from(code as u8))
 }
@@ -1,9 +1,11 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
 use dfiles::aspects;
 use dfiles::containermanager::ContainerManager;
+use dfiles::dockerfile::Instruction;
 
 #[derive(Clone)]
 struct Drawio {}
@@ -15,32 +17,33 @@ impl aspects::ContainerAspect for Drawio {
 
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
         vec![aspects::DockerfileSnippet {
-            order: 91,
-            content: r#"WORKDIR /opt/
-RUN curl -L https://github.com/jgraph/drawio-desktop/releases/download/v16.5.1/drawio-amd64-16.5.1.deb > /opt/drawio.deb && \
-    dpkg --force-depends -i /opt/drawio.deb  ; rm /opt/drawio.deb
-RUN apt-get update && apt-get --fix-broken install -y \
+            phase: aspects::Phase::AppInstall,
+            instructions: vec![
+                Instruction::Workdir(String::from("/opt/")),
+                Instruction::Add {
+                    src: String::from(
+                        "https://github.com/jgraph/drawio-desktop/releases/download/v16.5.1/drawio-amd64-16.5.1.deb",
+                    ),
+                    dest: String::from("/opt/drawio.deb"),
+                },
+                Instruction::run("dpkg --force-depends -i /opt/drawio.deb  ; rm /opt/drawio.deb"),
+            ],
+            content: r#"RUN apt-get update && apt-get --fix-broken install -y \
   && apt-get purge --autoremove \
   && rm -rf /var/lib/apt/lists/* \
   && rm -rf /src/*.deb "#.into(),
-        },
-            aspects::DockerfileSnippet {
-                order: 92,
-                content: String::from(
-                    r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    libgbm1 \
-    libasound2 \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
-                ),
-            },
-        ]
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::new(
+            aspects::Phase::AppInstall,
+            &["libgbm1", "libasound2"],
+        )]
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
     let container_path = format!("{}/.config/draw.io/", home);
 
@@ -63,5 +66,7 @@ fn main() -> Result<()> {
         None,
     )?;
 
-    mgr.execute().context("executing drawio in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing drawio in container")?;
+    Ok(ExitCode::from(code as u8))
 }
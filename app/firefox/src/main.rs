@@ -1,9 +1,11 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
 use dfiles::aspects;
 use dfiles::containermanager::ContainerManager;
+use dfiles::dockerfile::Instruction;
 
 #[derive(Clone)]
 struct Firefox {}
@@ -16,35 +18,32 @@ impl aspects::ContainerAspect for Firefox {
     }
 
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
-        vec![
-            aspects::DockerfileSnippet {
-                order: 91,
-                content: format!(
-                    r#"WORKDIR /opt/
-ADD https://archive.mozilla.org/pub/firefox/releases/{release}/linux-x86_64/en-US/firefox-{release}.tar.bz2 ./
-RUN tar -xjvf /opt/firefox-{release}.tar.bz2
-RUN ln -sf /opt/firefox/firefox-bin /usr/local/bin/firefox"#,
-                    release = VERSION,
-                ),
-            },
-            aspects::DockerfileSnippet {
-                order: 90,
-                content: String::from(
-                    r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    firefox-esr \
-    libasound2 \
-    libxt6 \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
-                ),
-            },
-        ]
+        vec![aspects::DockerfileSnippet {
+            phase: aspects::Phase::AppInstall,
+            instructions: vec![
+                Instruction::Workdir(String::from("/opt/")),
+                Instruction::Add {
+                    src: format!(
+                        "https://archive.mozilla.org/pub/firefox/releases/{release}/linux-x86_64/en-US/firefox-{release}.tar.bz2",
+                        release = VERSION,
+                    ),
+                    dest: String::from("./"),
+                },
+                Instruction::run(&format!("tar -xjvf /opt/firefox-{}.tar.bz2", VERSION)),
+                Instruction::run("ln -sf /opt/firefox/firefox-bin /usr/local/bin/firefox"),
+            ],
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::new(
+            aspects::Phase::AppInstall,
+            &["firefox-esr", "libasound2", "libxt6"],
+        )]
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
     let container_path = format!("{}/.mozilla/firefox/profile", home);
 
@@ -62,17 +61,14 @@ fn main() -> Result<()> {
             Box::new(aspects::DBus {}),
             Box::new(aspects::Shm {}),
         ],
-        vec![
-            "/opt/firefox/firefox-bin",
-            "--no-remote",
-            "--profile",
-            &container_path,
-        ]
-        .into_iter()
-        .map(String::from)
-        .collect(),
+        vec!["/opt/firefox/firefox-bin", "--profile", &container_path]
+            .into_iter()
+            .map(String::from)
+            .collect(),
         Some(String::from("bookworm")),
     )?;
 
-    mgr.execute().context("executing firefox in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing firefox in container")?;
+    Ok(ExitCode::from(code as u8))
 }
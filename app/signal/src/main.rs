@@ -1,4 +1,5 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
@@ -14,38 +15,33 @@ impl aspects::ContainerAspect for Signal {
     }
 
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
-        vec![
-            aspects::DockerfileSnippet {
-                order: 90,
-                content: String::from(
-                    r#"RUN apt-get update && apt-get install -y --no-install-recommends \
-        libgtk-3-0 \
-        libpango1.0-0 \
-        libcanberra-gtk* \
-        hicolor-icon-theme \
-        libgl1-mesa-dri \
-        libgl1-mesa-glx \
-        libv4l-0 \
-        openjdk-11-jre \
-        fonts-symbola \
-        xdg-utils \
-    && curl -sSL https://updates.signal.org/desktop/apt/keys.asc | apt-key add - \
-    && echo "deb [arch=amd64] https://updates.signal.org/desktop/apt xenial main" | tee -a /etc/apt/sources.list.d/signal-xenial.list \
-    && apt-get update && apt-get install -y --no-install-recommends \
-        signal-desktop \
-    && rm -rf /var/lib/apt/lists/* \
-    && rm -rf /src/*.deb "#,
-                ),
-            },
-            aspects::DockerfileSnippet {
-                order: 91,
-                content: String::from(r#"RUN chmod 4755 /opt/Signal/chrome-sandbox"#),
-            },
-        ]
+        vec![aspects::DockerfileSnippet {
+            phase: aspects::Phase::AppInstall,
+            content: String::from(r#"RUN chmod 4755 /opt/Signal/chrome-sandbox"#),
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::new(
+            aspects::Phase::AppInstall,
+            &[
+                "libgtk-3-0",
+                "libpango1.0-0",
+                "libcanberra-gtk*",
+                "hicolor-icon-theme",
+                "libgl1-mesa-dri",
+                "libgl1-mesa-glx",
+                "libv4l-0",
+                "openjdk-11-jre",
+                "fonts-symbola",
+                "xdg-utils",
+                "signal-desktop",
+            ],
+        )]
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
     let container_path = format!("{}/.config/Signal/", home);
 
@@ -55,6 +51,20 @@ fn main() -> Result<()> {
         vec![container_path],
         vec![
             Box::new(Signal {}),
+            Box::new(aspects::AptRepository {
+                name: String::from("signal"),
+                url: String::from("https://updates.signal.org/desktop/apt"),
+                key: aspects::AptKey::Url(String::from(
+                    "https://updates.signal.org/desktop/apt/keys.asc",
+                )),
+                // Open Whisper Systems <support@whispersystems.org>
+                fingerprint: Some(String::from(
+                    "DBA3 6B51 81D0 C816 F630  E889 D980 A174 57F6 FB06",
+                )),
+                suite: String::from("xenial"),
+                components: vec![String::from("main")],
+                architecture: Some(String::from("amd64")),
+            }),
             Box::new(aspects::Name("signal".to_string())),
             Box::new(aspects::PulseAudio {}),
             Box::new(aspects::CurrentUser::detect().context("detecting current user")?),
@@ -70,5 +80,7 @@ fn main() -> Result<()> {
         Some("bullseye".to_string()),
     )?;
 
-    mgr.execute().context("executing signal in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing signal in container")?;
+    Ok(ExitCode::from(code as u8))
 }
@@ -1,4 +1,5 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
@@ -16,33 +17,29 @@ impl aspects::ContainerAspect for Skype {
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
         vec![
             aspects::DockerfileSnippet {
-                order: 75,
+                phase: aspects::Phase::AppInstall,
                 content: String::from(
                     r#"COPY /etc/fonts/local.conf /etc/fonts/local.conf
 RUN chmod 655 /etc/fonts
 RUN chmod 644 /etc/fonts/local.conf"#,
                 ),
+                ..Default::default()
             },
             aspects::DockerfileSnippet {
-                order: 91,
-                content: r#"# Add the skype debian repo
-RUN curl -sSL https://repo.skype.com/data/SKYPE-GPG-KEY | apt-key add -
-RUN echo "deb [arch=amd64] https://repo.skype.com/deb stable main" > /etc/apt/sources.list.d/skype.list
-
-RUN apt-get update && apt-get -y install \
-    --no-install-recommends \
-        skypeforlinux \
-    && apt-get purge --autoremove \
-    && rm -rf /var/lib/apt/lists/*
-"#.to_string(),
-            },
-            aspects::DockerfileSnippet {
-                order: 92,
+                phase: aspects::Phase::AppInstall,
                 content: r#"COPY /run-skype-and-wait-for-exit /usr/local/bin
-RUN chmod 755 /usr/local/bin/run-skype-and-wait-for-exit"#.to_string(),
+RUN chmod 755 /usr/local/bin/run-skype-and-wait-for-exit"#
+                    .to_string(),
+                ..Default::default()
             },
         ]
     }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::new(
+            aspects::Phase::AppInstall,
+            &["skypeforlinux"],
+        )]
+    }
     fn container_files(&self) -> Vec<aspects::ContainerFile> {
         vec![
             aspects::ContainerFile {
@@ -95,7 +92,7 @@ while ps -C skypeforlinux >/dev/null;do sleep 3;done "#
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
     let container_path = format!("{}/.config/skypeforlinux", home);
 
@@ -107,6 +104,20 @@ fn main() -> Result<()> {
         vec![container_path],
         vec![
             Box::new(Skype {}),
+            Box::new(aspects::AptRepository {
+                name: String::from("skype"),
+                url: String::from("https://repo.skype.com/deb"),
+                key: aspects::AptKey::Url(String::from(
+                    "https://repo.skype.com/data/SKYPE-GPG-KEY",
+                )),
+                // Skype Linux Client Repository <se-um@microsoft.com>
+                fingerprint: Some(String::from(
+                    "D404 0146 BE39 7250 9FD5  7FC7 1F30 45A5 DF75 87C3",
+                )),
+                suite: String::from("stable"),
+                components: vec![String::from("main")],
+                architecture: Some(String::from("amd64")),
+            }),
             Box::new(aspects::Name("skype".to_string())),
             Box::new(aspects::CurrentUser::detect().context("detecting current user")?),
             Box::new(aspects::PulseAudio {}),
@@ -123,5 +134,7 @@ fn main() -> Result<()> {
         None,
     )?;
 
-    mgr.execute().context("executing skype in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing skype in container")?;
+    Ok(ExitCode::from(code as u8))
 }
@@ -1,4 +1,5 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
@@ -15,7 +16,7 @@ impl aspects::ContainerAspect for Steam {
 
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
         vec![aspects::DockerfileSnippet {
-            order: 91,
+            phase: aspects::Phase::AppInstall,
             content: r#"RUN dpkg --add-architecture i386
 RUN sed -i -e 's|main|main contrib non-free|' /etc/apt/sources.list
 RUN echo steam steam/question select "I AGREE" | sudo debconf-set-selections
@@ -26,11 +27,12 @@ RUN apt-get update && yes 'I AGREE' | apt-get install -y \
     && rm -rf /var/lib/apt/lists/* \
     && rm -rf /src/*.deb"#
                 .to_string(),
+            ..Default::default()
         }]
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
     let container_path = format!("{}/.steam/", home);
 
@@ -58,5 +60,7 @@ fn main() -> Result<()> {
         None,
     )?;
 
-    mgr.execute().context("executing steam in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing steam in container")?;
+    Ok(ExitCode::from(code as u8))
 }
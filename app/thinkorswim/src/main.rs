@@ -1,9 +1,11 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
 use dfiles::aspects;
 use dfiles::containermanager::ContainerManager;
+use dfiles::dockerfile::Instruction;
 
 #[derive(Clone)]
 struct Thinkorswim {
@@ -18,27 +20,26 @@ impl aspects::ContainerAspect for Thinkorswim {
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
         vec![
             aspects::DockerfileSnippet {
-                order: 90,
-                content: r#"
-RUN apt-get update && apt-get install -y --no-install-recommends \
-        openjdk-11-jre \
-    && rm -rf /var/lib/apt/lists/* \
-    && rm -rf /src/*.deb"#
-                    .to_string(),
-            },
-            aspects::DockerfileSnippet {
-                order: 91,
+                phase: aspects::Phase::AppInstall,
+                instructions: vec![
+                    Instruction::Workdir(String::from("/opt/")),
+                    Instruction::Add {
+                        src: String::from(
+                            "https://mediaserver.thinkorswim.com/installer/InstFiles/thinkorswim_installer.sh",
+                        ),
+                        dest: String::from("./"),
+                    },
+                ],
                 content: format!(
-                    r#"WORKDIR /opt/
-ADD https://mediaserver.thinkorswim.com/installer/InstFiles/thinkorswim_installer.sh ./
-RUN chown -R {name}.{name} /opt/thinkorswim_installer.sh
+                    r#"RUN chown -R {name}.{name} /opt/thinkorswim_installer.sh
 RUN chmod u+wrx /opt/thinkorswim_installer.sh
 "#,
                     name = self.user_name,
                 ),
+                ..Default::default()
             },
             aspects::DockerfileSnippet {
-                order: 97,
+                phase: aspects::Phase::AppInstall,
                 content: format!(
                     r#"
 COPY ./opt/run-thinkorswim.sh /run-thinkorswim.sh
@@ -48,9 +49,16 @@ ENTRYPOINT ["/run-thinkorswim.sh"]
 "#,
                     name = self.user_name,
                 ),
+                ..Default::default()
             },
         ]
     }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::new(
+            aspects::Phase::AppInstall,
+            &["openjdk-11-jre"],
+        )]
+    }
 
     fn container_files(&self) -> Vec<aspects::ContainerFile> {
         vec![aspects::ContainerFile {
@@ -60,7 +68,7 @@ ENTRYPOINT ["/run-thinkorswim.sh"]
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
 
     let version = env!("CARGO_PKG_VERSION");
@@ -98,5 +106,9 @@ fn main() -> Result<()> {
         Some(String::from("bullseye")),
     )?;
 
-    mgr.execute().context("executing thinkorswim in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr
+        .execute()
+        .context("executing thinkorswim in container")?;
+    Ok(ExitCode::from(code as u8))
 }
@@ -1,9 +1,11 @@
 use std::env;
+use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 
 use dfiles::aspects;
 use dfiles::containermanager::ContainerManager;
+use dfiles::dockerfile::Instruction;
 
 #[derive(Clone)]
 struct Zoom {}
@@ -15,20 +17,28 @@ impl aspects::ContainerAspect for Zoom {
 
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
         vec![aspects::DockerfileSnippet {
-            order: 91,
-            content: r#"WORKDIR /opt/
-RUN curl -L https://zoom.us/client/latest/zoom_amd64.deb -o /opt/zoom_amd64.deb && \
-    dpkg --force-depends -i /opt/zoom_amd64.deb && rm /opt/zoom_amd64.deb
-RUN apt-get update && apt-get --fix-broken install -y \
+            phase: aspects::Phase::AppInstall,
+            instructions: vec![
+                Instruction::Workdir(String::from("/opt/")),
+                Instruction::Add {
+                    src: String::from("https://zoom.us/client/latest/zoom_amd64.deb"),
+                    dest: String::from("/opt/zoom_amd64.deb"),
+                },
+                Instruction::run(
+                    "dpkg --force-depends -i /opt/zoom_amd64.deb && rm /opt/zoom_amd64.deb",
+                ),
+            ],
+            content: r#"RUN apt-get update && apt-get --fix-broken install -y \
   && apt-get purge --autoremove \
   && rm -rf /var/lib/apt/lists/* \
   && rm -rf /src/*.deb "#
                 .to_string(),
+            ..Default::default()
         }]
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let home = env::var("HOME").expect("HOME must be set");
 
     let version = env!("CARGO_PKG_VERSION");
@@ -55,5 +65,7 @@ fn main() -> Result<()> {
         None,
     )?;
 
-    mgr.execute().context("executing zoom in container")
+    mgr.set_app_version(env!("CARGO_PKG_VERSION"));
+    let code = mgr.execute().context("executing zoom in container")?;
+    Ok(ExitCode::from(code as u8))
 }
@@ -10,12 +10,81 @@ use serde::{Deserialize, Serialize};
 use users;
 
 use super::dirs;
+use super::dockerfile::{self, Instruction};
 use super::entrypoint;
 use super::error::{Error, Result};
+use super::packages::PackageManager;
+use super::runtime;
 
+pub use super::phase::Phase;
+
+/// A block of Dockerfile instructions, followed by any raw Dockerfile text in `content`. Blocks
+/// are placed by phase and then by their `after` and `before` constraints, which name other
+/// aspects, or `*` for every other aspect in the phase. Blocks with the same `key` are only
+/// emitted once, and must be identical.
+#[derive(Default, PartialEq)]
 pub struct DockerfileSnippet {
-    pub order: u8,
+    pub phase: Phase,
+    pub instructions: Vec<Instruction>,
     pub content: String,
+    pub after: Vec<String>,
+    pub before: Vec<String>,
+    pub key: Option<String>,
+}
+
+impl DockerfileSnippet {
+    /// Renders the snippet as Dockerfile text.
+    pub fn render(&self) -> String {
+        let mut rendered = dockerfile::render(&self.instructions);
+        if !self.content.is_empty() {
+            if !rendered.is_empty() {
+                rendered.push('\n');
+            }
+            rendered.push_str(&self.content);
+        }
+        rendered
+    }
+}
+
+/// Distro packages an aspect needs installed, named as in Debian and translated by the base image's
+/// [`PackageManager`]. Packages from every aspect are merged into a single install step per phase,
+/// placed at the start of the phase, and each package is installed once in the earliest phase that
+/// asks for it. Lists marked `native` already use the base image's own package names and are
+/// installed as given. `Packages` can also be used as an aspect of its own to add packages to an
+/// app.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Packages {
+    pub phase: Phase,
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub native: bool,
+}
+
+impl Packages {
+    pub fn new(phase: Phase, names: &[&str]) -> Packages {
+        Packages {
+            phase,
+            names: names.iter().map(|n| n.to_string()).collect(),
+            native: false,
+        }
+    }
+
+    /// Packages named as in the base image's distro, which are not translated.
+    pub fn native(phase: Phase, names: &[&str]) -> Packages {
+        Packages {
+            native: true,
+            ..Packages::new(phase, names)
+        }
+    }
+}
+
+impl ContainerAspect for Packages {
+    fn name(&self) -> String {
+        String::from("Packages")
+    }
+    fn packages(&self) -> Vec<Packages> {
+        vec![self.clone()]
+    }
 }
 
 pub struct ContainerFile {
@@ -37,12 +106,31 @@ pub trait ContainerAspect: dyn_clone::DynClone {
     fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
         Vec::new()
     }
+    /// The Dockerfile snippets for a base image using `package_manager`. Aspects whose build steps
+    /// differ between distros override this rather than `dockerfile_snippets`.
+    fn dockerfile_snippets_for(&self, _package_manager: PackageManager) -> Vec<DockerfileSnippet> {
+        self.dockerfile_snippets()
+    }
+    fn packages(&self) -> Vec<Packages> {
+        Vec::new()
+    }
+    /// The package manager of the base image, if this aspect provides one.
+    fn package_manager(&self) -> Option<PackageManager> {
+        None
+    }
     fn container_files(&self) -> Vec<ContainerFile> {
         Vec::new()
     }
     fn entrypoint_snippets(&self) -> Result<Vec<entrypoint::ScriptSnippet>> {
         Ok(Vec::new())
     }
+    /// The entrypoint snippets for a container of a base image using `package_manager`.
+    fn entrypoint_snippets_for(
+        &self,
+        _package_manager: PackageManager,
+    ) -> Result<Vec<entrypoint::ScriptSnippet>> {
+        self.entrypoint_snippets()
+    }
 }
 
 dyn_clone::clone_trait_object!(ContainerAspect);
@@ -81,26 +169,18 @@ impl ContainerAspect for PulseAudio {
         .map(String::from)
         .collect())
     }
-    fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
-        vec![DockerfileSnippet {
-            order: 70,
-            content: String::from(
-                r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    libavcodec-extra \
-    libpulse0 \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
-            ),
-        }]
+    fn packages(&self) -> Vec<Packages> {
+        vec![Packages::new(
+            Phase::SystemPackages,
+            &["libavcodec-extra", "libpulse0"],
+        )]
     }
     fn entrypoint_snippets(&self) -> Result<Vec<entrypoint::ScriptSnippet>> {
         let uid = users::get_current_uid();
         Ok(vec![
             entrypoint::ScriptSnippet {
                 description: "configure pulseaudio client to connect to host daemon".to_string(),
-                order: 50,
+                phase: Phase::SystemPackages,
                 snippet: format!(
                     r#"cat << EOF > /etc/pulse/client.conf
 # Connect to the host's server using the mounted UNIX socket
@@ -118,6 +198,7 @@ chmod 655 /etc/pulse
 chmod 644 /etc/pulse/client.conf"#,
                     uid = uid,
                 ),
+                ..Default::default()
             },
             entrypoint::group_setup("audio")?,
         ])
@@ -162,18 +243,8 @@ impl ContainerAspect for X11 {
         .map(String::from)
         .collect())
     }
-    fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
-        vec![DockerfileSnippet {
-            order: 72,
-            content: String::from(
-                r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    libxtst6 \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
-            ),
-        }]
+    fn packages(&self) -> Vec<Packages> {
+        vec![Packages::new(Phase::SystemPackages, &["libxtst6"])]
     }
 }
 
@@ -203,24 +274,16 @@ impl ContainerAspect for Video {
             .flat_map(|s| vec![String::from("--device"), s.to_string()])
             .collect())
     }
-    fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
-        vec![DockerfileSnippet {
-            order: 72,
-            content: String::from(
-                r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    libpci3 \
-    libpciaccess0 \
-    libegl1 \
-    libgl1 \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
-            ),
-        }]
+    fn packages(&self) -> Vec<Packages> {
+        vec![Packages::new(
+            Phase::SystemPackages,
+            &["libpci3", "libpciaccess0", "libegl1", "libgl1"],
+        )]
     }
     fn entrypoint_snippets(&self) -> Result<Vec<entrypoint::ScriptSnippet>> {
-        Ok(vec![entrypoint::group_setup("video")?])
+        let mut group = entrypoint::group_setup("video")?;
+        group.after.push(String::from("PulseAudio"));
+        Ok(vec![group])
     }
 }
 
@@ -248,19 +311,328 @@ impl ContainerAspect for DBus {
         .map(String::from)
         .collect())
     }
+    fn packages(&self) -> Vec<Packages> {
+        vec![Packages::new(Phase::SystemPackages, &["dbus-x11"])]
+    }
+}
+
+/// Where an [`AptRepository`] signing key comes from.
+#[derive(Clone)]
+pub enum AptKey {
+    /// Downloaded at build time.
+    Url(String),
+    /// Shipped in the build context, either ASCII armored or binary.
+    Bytes(Vec<u8>),
+}
+
+/// A third party APT repository trusted only through its own keyring in `/etc/apt/keyrings`,
+/// rather than with the deprecated `apt-key`. If a fingerprint is given the build fails unless
+/// the key matches it. Only useful on Debian based images.
+#[derive(Clone)]
+pub struct AptRepository {
+    /// Names the keyring and sources list files.
+    pub name: String,
+    pub url: String,
+    pub key: AptKey,
+    pub fingerprint: Option<String>,
+    pub suite: String,
+    pub components: Vec<String>,
+    pub architecture: Option<String>,
+}
+
+impl AptRepository {
+    fn key_path(&self) -> String {
+        format!("/tmp/{}.key", self.name)
+    }
+
+    fn keyring_path(&self) -> String {
+        format!("/etc/apt/keyrings/{}.gpg", self.name)
+    }
+
+    fn context_key_path(&self) -> String {
+        format!("apt-keys/{}.key", self.name)
+    }
+
+    /// The `deb` line for the sources list.
+    pub fn source(&self) -> String {
+        let mut options = Vec::new();
+        if let Some(arch) = &self.architecture {
+            options.push(format!("arch={}", arch));
+        }
+        options.push(format!("signed-by={}", self.keyring_path()));
+        format!(
+            "deb [{}] {} {} {}",
+            options.join(" "),
+            self.url,
+            self.suite,
+            self.components.join(" ")
+        )
+    }
+}
+
+impl ContainerAspect for AptRepository {
+    fn name(&self) -> String {
+        format!("AptRepository: {}", self.name)
+    }
+    fn packages(&self) -> Vec<Packages> {
+        vec![Packages::new(
+            Phase::SystemPackages,
+            &["ca-certificates", "gnupg"],
+        )]
+    }
     fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
-        vec![DockerfileSnippet {
-            order: 71,
-            content: String::from(
-                r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    dbus-x11 \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
+        let fetch = match &self.key {
+            AptKey::Url(url) => Instruction::Add {
+                src: url.clone(),
+                dest: self.key_path(),
+            },
+            AptKey::Bytes(_) => Instruction::Copy {
+                src: self.context_key_path(),
+                dest: self.key_path(),
+            },
+        };
+
+        let mut commands = vec![
+            String::from("mkdir -p /etc/apt/keyrings"),
+            String::from("export GNUPGHOME=\"$(mktemp -d)\""),
+        ];
+        let fingerprint: Option<String> = self.fingerprint.as_ref().map(|f| {
+            f.chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>()
+                .to_uppercase()
+        });
+        if let Some(fingerprint) = &fingerprint {
+            commands.push(format!(
+                r#"{{ gpg --batch --with-colons --import-options show-only --import {key} \
+      | grep -q "^fpr:::::::::{fingerprint}:$" \
+      || {{ echo "{name}: signing key does not match fingerprint {fingerprint}" >&2; exit 1; }}; }}"#,
+                key = self.key_path(),
+                fingerprint = fingerprint,
+                name = self.name,
+            ));
+        }
+        // with a pinned key, any other keys shipped alongside it are left out of the keyring
+        commands.extend(vec![
+            format!("gpg --batch --import {}", self.key_path()),
+            format!(
+                "gpg --batch --export {}> {}",
+                fingerprint.map(|f| f + " ").unwrap_or_default(),
+                self.keyring_path()
+            ),
+            format!("rm -rf \"$GNUPGHOME\" {}", self.key_path()),
+            format!(
+                "echo \"{}\" > /etc/apt/sources.list.d/{}.list",
+                self.source(),
+                self.name
             ),
+        ]);
+
+        vec![DockerfileSnippet {
+            phase: Phase::SystemPackages,
+            instructions: vec![fetch, Instruction::Run(commands)],
+            ..Default::default()
         }]
     }
+    fn container_files(&self) -> Vec<ContainerFile> {
+        match &self.key {
+            AptKey::Bytes(bytes) => vec![ContainerFile {
+                container_path: format!("./{}", self.context_key_path()),
+                contents: bytes.clone(),
+            }],
+            AptKey::Url(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod apt_repository_should {
+    use super::*;
+
+    fn repository(key: AptKey, fingerprint: Option<&str>) -> AptRepository {
+        AptRepository {
+            name: String::from("example"),
+            url: String::from("https://example.com/deb"),
+            key,
+            fingerprint: fingerprint.map(String::from),
+            suite: String::from("stable"),
+            components: vec![String::from("main")],
+            architecture: Some(String::from("amd64")),
+        }
+    }
+
+    #[test]
+    fn trust_keys_only_for_their_repository() {
+        let repo = repository(
+            AptKey::Url(String::from("https://example.com/key.asc")),
+            None,
+        );
+        let rendered = repo.dockerfile_snippets()[0].render();
+        assert!(rendered.starts_with("ADD https://example.com/key.asc /tmp/example.key\n"));
+        assert!(rendered.contains(
+            "echo \"deb [arch=amd64 signed-by=/etc/apt/keyrings/example.gpg] https://example.com/deb stable main\" > /etc/apt/sources.list.d/example.list"
+        ));
+        assert!(!rendered.contains("apt-key"));
+        assert!(!rendered.contains("fpr:"));
+        assert!(rendered.contains("gpg --batch --export > /etc/apt/keyrings/example.gpg"));
+    }
+
+    #[test]
+    fn check_fingerprints_of_bundled_keys() {
+        let repo = repository(AptKey::Bytes(b"key".to_vec()), Some("abcd 1234"));
+        let rendered = repo.dockerfile_snippets()[0].render();
+        assert!(rendered.starts_with("COPY apt-keys/example.key /tmp/example.key\n"));
+        assert!(rendered.contains("grep -q \"^fpr:::::::::ABCD1234:$\""));
+        assert!(rendered.contains("gpg --batch --export ABCD1234 > /etc/apt/keyrings/example.gpg"));
+        assert_eq!(
+            repo.container_files()[0].container_path,
+            "./apt-keys/example.key"
+        );
+    }
+}
+
+/// Downloads a file at build time and aborts the build unless its SHA-256 checksum matches.
+/// Snippets using the file should come after it, either by listing the download ahead of the
+/// app's aspect or with an `after` constraint on its name.
+#[derive(Clone)]
+pub struct Download {
+    url: String,
+    sha256: String,
+    destination: String,
+}
+
+impl Download {
+    pub fn new(url: &str, sha256: &str, destination: &str) -> Result<Download> {
+        let sha256 = sha256.trim().to_lowercase();
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::InvalidChecksum(sha256));
+        }
+        Ok(Download {
+            url: url.to_string(),
+            sha256,
+            destination: destination.to_string(),
+        })
+    }
+
+    pub(crate) fn commands(&self) -> Vec<String> {
+        let quote = runtime::shell_quote;
+        let mut commands = Vec::new();
+        if let Some(dir) = Path::new(&self.destination).parent() {
+            if !dir.as_os_str().is_empty() {
+                commands.push(format!("mkdir -p {}", quote(&dir.to_string_lossy())));
+            }
+        }
+        commands.push(format!(
+            "curl -fsSL {} -o {}",
+            quote(&self.url),
+            quote(&self.destination)
+        ));
+        commands.push(format!(
+            "echo {} | sha256sum -c -",
+            quote(&format!("{}  {}", self.sha256, self.destination))
+        ));
+        commands
+    }
+}
+
+impl ContainerAspect for Download {
+    fn name(&self) -> String {
+        format!("Download: {}", self.destination)
+    }
+    fn packages(&self) -> Vec<Packages> {
+        vec![Packages::new(
+            Phase::SystemPackages,
+            &["ca-certificates", "curl"],
+        )]
+    }
+    fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
+        vec![DockerfileSnippet {
+            phase: Phase::AppInstall,
+            instructions: vec![Instruction::Run(self.commands())],
+            key: Some(format!("download:{}", self.destination)),
+            ..Default::default()
+        }]
+    }
+}
+
+#[cfg(test)]
+mod download_should {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    const BODY: &[u8] = b"vendor tarball";
+    const BODY_SHA256: &str = "c2c80ed7990470edbcbbcedf3747038ece44786469b278af282f86fac5e50b38";
+
+    /// Serves `BODY` to every request on a local port.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    BODY.len()
+                );
+                let _ = stream.write_all(BODY);
+            }
+        });
+        format!("http://{}/vendor.tar.bz2", addr)
+    }
+
+    fn run(download: &Download) -> Result<bool> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(download.commands().join(" && "))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        Ok(status.success())
+    }
+
+    fn fetch(sha256: &str) -> Result<bool> {
+        let dir = tempfile::tempdir()?;
+        let destination = dir.path().join("opt/vendor.tar.bz2");
+        run(&Download::new(
+            &serve(),
+            sha256,
+            &destination.to_string_lossy(),
+        )?)
+    }
+
+    #[test]
+    fn verify_downloaded_files() -> Result<()> {
+        assert!(fetch(BODY_SHA256)?);
+        assert!(!fetch(&"0".repeat(64))?);
+        Ok(())
+    }
+
+    #[test]
+    fn quote_urls_and_destinations() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let destination = dir.path().join("opt dir/vendor $(id).tar.bz2");
+        let url = format!("{}?version=1&arch=x86_64", serve());
+        assert!(run(&Download::new(
+            &url,
+            BODY_SHA256,
+            &destination.to_string_lossy()
+        )?)?);
+        assert_eq!(fs::read(&destination)?, BODY);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_malformed_checksums() {
+        assert!(matches!(
+            Download::new("http://example.com/f", "abc", "/opt/f"),
+            Err(Error::InvalidChecksum(_))
+        ));
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -497,6 +869,14 @@ impl ContainerAspect for CurrentUser {
         format!("User: {}", &self.name)
     }
 
+    fn run_args(&self, _: Option<&ArgMatches>) -> Result<Vec<String>> {
+        // lets later launches exec into the running container as the same user
+        Ok(vec![
+            String::from("--label"),
+            format!("{}={}", runtime::USER_LABEL, self.name),
+        ])
+    }
+
     fn entrypoint_snippets(&self) -> Result<Vec<entrypoint::ScriptSnippet>> {
         let uid = users::get_current_uid();
         let gid = users::get_current_gid();
@@ -510,9 +890,9 @@ impl ContainerAspect for CurrentUser {
         };
         Ok(vec![entrypoint::ScriptSnippet {
             description: format!("create a user named {}", self.name),
-            order: 2,
+            phase: Phase::Base,
             snippet: format!(
-                r#"addgroup --gid {gid} {group}
+                r#"groupadd --gid {gid} {group}
 useradd --home-dir /home/{user} \
     --shell /bin/bash \
     --uid {uid} \
@@ -520,7 +900,7 @@ useradd --home-dir /home/{user} \
     {user}
 
 # NOTE: I don't remember why this was necessary...
-adduser {user} tty
+usermod --append --groups tty {user}
 
 mkdir -p /data /home/{user}
 # NOTE: we need to recursively chown all files in the /home/{user} directory
@@ -535,6 +915,7 @@ USER={user}"#,
                 user = name,
                 uid = uid,
             ),
+            ..Default::default()
         }])
     }
 }
@@ -559,20 +940,42 @@ impl ContainerAspect for Locale {
         "AutoLocale".to_string()
     }
     fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
-        let locale = format!("{}_{}.{}", self.language, self.territory, self.codeset);
+        self.dockerfile_snippets_for(PackageManager::default())
+    }
+    fn dockerfile_snippets_for(&self, package_manager: PackageManager) -> Vec<DockerfileSnippet> {
+        let locale = String::from(self);
+        let instructions = match package_manager {
+            PackageManager::Apt => vec![
+                Instruction::run(&format!(
+                    "echo '{} {}' > /etc/locale.gen",
+                    locale, self.codeset
+                )),
+                Instruction::run("locale-gen"),
+                Instruction::run(&format!("echo LANG=\"{}\" > /etc/default/locale", locale)),
+            ],
+            PackageManager::Dnf => vec![
+                package_manager.install(&[&format!("glibc-langpack-{}", self.language)]),
+                Instruction::run(&format!("echo LANG=\"{}\" > /etc/locale.conf", locale)),
+            ],
+            // musl has no locales to generate
+            PackageManager::Apk => Vec::new(),
+        };
         vec![DockerfileSnippet {
-            order: 88,
-            content: format!(
-                r#"RUN echo '{locale} {codeset}' > /etc/locale.gen
-RUN locale-gen
-RUN echo LANG="{locale}" > /etc/default/locale
-ENV LANG={locale}"#,
-                locale = locale,
-                codeset = self.codeset,
-            ),
+            phase: Phase::Locale,
+            instructions: instructions
+                .into_iter()
+                .chain(std::iter::once(Instruction::env("LANG", &locale)))
+                .collect(),
+            ..Default::default()
         }]
     }
     fn entrypoint_snippets(&self) -> Result<Vec<entrypoint::ScriptSnippet>> {
+        self.entrypoint_snippets_for(PackageManager::default())
+    }
+    fn entrypoint_snippets_for(
+        &self,
+        package_manager: PackageManager,
+    ) -> Result<Vec<entrypoint::ScriptSnippet>> {
         let mut snippets = Vec::new();
         let mut locale = String::from(self);
         if let Some(value) = env::var("LC_ALL")
@@ -583,17 +986,23 @@ ENV LANG={locale}"#,
             locale = value;
         }
 
-        snippets.push(entrypoint::ScriptSnippet {
-            description: "set a non-default entrypoint snippet".to_string(),
-            order: 80,
-            snippet: format!(
+        let setup = match package_manager {
+            PackageManager::Apt => format!(
                 r#"echo '{locale} {codeset}' > /etc/locale.gen
 locale-gen
 echo LANG="{locale}" > /etc/default/locale
-export LANG={locale}"#,
+"#,
                 locale = locale,
                 codeset = self.codeset,
             ),
+            PackageManager::Dnf => format!("echo LANG=\"{}\" > /etc/locale.conf\n", locale),
+            PackageManager::Apk => String::new(),
+        };
+        snippets.push(entrypoint::ScriptSnippet {
+            description: "set a non-default entrypoint snippet".to_string(),
+            phase: Phase::Locale,
+            snippet: format!("{}export LANG={}", setup, locale),
+            ..Default::default()
         });
         Ok(snippets)
     }
@@ -652,6 +1061,23 @@ mod locale_should {
         );
         Ok(())
     }
+
+    #[test]
+    fn set_lang_in_image() -> Result<()> {
+        let locale = Locale::try_from(&String::from("en_US.UTF-8"))?;
+        for package_manager in [
+            PackageManager::Apt,
+            PackageManager::Dnf,
+            PackageManager::Apk,
+        ] {
+            let snippets = locale.dockerfile_snippets_for(package_manager);
+            assert_eq!(
+                snippets[0].instructions.last(),
+                Some(&Instruction::env("LANG", "en_US.UTF-8"))
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -663,16 +1089,23 @@ impl ContainerAspect for Timezone {
     }
     fn dockerfile_snippets(&self) -> Vec<DockerfileSnippet> {
         vec![DockerfileSnippet {
-            order: 88,
-            content: format!(
-                r#"ENV TZ={tz}
-RUN ln -snf /usr/share/zoneinfo/{tz} /etc/localtime
-RUN echo {tz} > /etc/timezone
-"#,
-                tz = self.0,
-            ),
+            phase: Phase::Locale,
+            instructions: vec![
+                Instruction::env("TZ", &self.0),
+                Instruction::run(&format!(
+                    "ln -snf /usr/share/zoneinfo/{} /etc/localtime",
+                    self.0
+                )),
+                Instruction::run(&format!("echo {} > /etc/timezone", self.0)),
+            ],
+            after: vec![String::from("AutoLocale")],
+            ..Default::default()
         }]
     }
+    fn packages(&self) -> Vec<Packages> {
+        // not every base image ships the zoneinfo files
+        vec![Packages::new(Phase::Locale, &["tzdata"])]
+    }
     fn run_args(&self, matches: Option<&ArgMatches>) -> Result<Vec<String>> {
         let mut timezone = self.0.clone();
         if let Some(m) = matches {
@@ -689,7 +1122,7 @@ RUN echo {tz} > /etc/timezone
         Ok(vec![
             entrypoint::ScriptSnippet {
                 description: "configure timezone based on TZ variable in host".to_string(),
-                order: 60,
+                phase: Phase::Locale,
                 snippet: format!(
                     r#"export TZ={tz}
 ln -snf /usr/share/zoneinfo/{tz} /etc/localtime
@@ -697,6 +1130,8 @@ echo {tz} > /etc/timezone
 "#,
                     tz = tz,
                 ),
+                after: vec![String::from("AutoLocale")],
+                ..Default::default()
             },
             entrypoint::group_setup("audio")?,
         ])
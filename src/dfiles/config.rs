@@ -2,12 +2,15 @@ use std::convert::TryFrom;
 use std::fs;
 use std::io::Write;
 
+use clap::parser::{MatchesError, ValuesRef};
 use clap::{Arg, ArgAction, ArgMatches};
 use serde::{Deserialize, Serialize};
 
 use super::aspects;
 use super::dirs;
 use super::error::{Error, Result};
+use super::registry;
+use super::runtime;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
@@ -17,6 +20,8 @@ pub struct Config {
     pub cpu_shares: Option<aspects::CPUShares>,
     pub network: Option<aspects::Network>,
     pub locale: Option<aspects::Locale>,
+    pub runtime: Option<runtime::Runtime>,
+    pub registry: Option<registry::Registry>,
 }
 
 impl Config {
@@ -28,20 +33,28 @@ impl Config {
             cpu_shares: None,
             network: None,
             locale: None,
+            runtime: None,
+            registry: None,
         }
     }
 
     pub fn save(&self, application: Option<&str>, profile: Option<&str>) -> Result<()> {
+        self.check_profile_layer(profile)?;
         let existing_config = Config::load_layer(application, profile)?;
-        let merged = existing_config.merge(self, true);
+        existing_config
+            .merge(self, true)
+            .write_layer(application, profile)
+    }
 
+    /// Writes this config as the config file of the given layer, replacing what was there.
+    fn write_layer(&self, application: Option<&str>, profile: Option<&str>) -> Result<()> {
         let config_dir = dirs::get_config_dir(application, profile)?;
         fs::create_dir_all(&config_dir)?;
 
         let path = config_dir.join("config.yaml");
         let mut config_file = fs::File::create(path)?;
 
-        let s = serde_yaml::to_string(&merged).map_err(|_| Error::FailedToSaveConfig)?;
+        let s = serde_yaml::to_string(self).map_err(|_| Error::FailedToSaveConfig)?;
         config_file.write_all(&s.into_bytes())?;
 
         Ok(())
@@ -63,13 +76,49 @@ impl Config {
         Ok(cfg)
     }
 
+    /// Refuses build time settings in a profile's config layer; all of an app's profiles run the
+    /// same image, so those can only be set for the whole app.
+    fn check_profile_layer(&self, profile: Option<&str>) -> Result<()> {
+        match profile {
+            Some(profile) if !self.build_values().is_empty() => Err(Error::ProfileBuildConfig {
+                profile: profile.to_string(),
+                values: self.build_values().join(", "),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the build time settings of a profile's config layer to the app's layer, unless the
+    /// app's layer sets them already.
+    fn move_build_config(&mut self, app_config: &mut Config) {
+        if let Some(timezone) = self.timezone.take() {
+            app_config.timezone.get_or_insert(timezone);
+        }
+        if let Some(locale) = self.locale.take() {
+            app_config.locale.get_or_insert(locale);
+        }
+    }
+
     pub fn load(application: &str, profile: Option<&str>) -> Result<Config> {
         // load dfiles global config if it exists
         let global_config = Config::load_layer(None, None)?;
         // load application global config if it exists
-        let app_config = Config::load_layer(Some(application), None)?;
+        let mut app_config = Config::load_layer(Some(application), None)?;
         // load application profile config if profile is specified and it exists
-        let profile_config = Config::load_layer(Some(application), profile)?;
+        let mut profile_config = Config::load_layer(Some(application), profile)?;
+        if let Some(profile) = profile {
+            // older versions saved `config` without `--profile` into the default profile
+            if !profile_config.build_values().is_empty() {
+                log::warn!(
+                    "profile `{}` sets {}, which apply to all profiles; moving them to the app config",
+                    profile,
+                    profile_config.build_values().join(", ")
+                );
+                profile_config.move_build_config(&mut app_config);
+                app_config.write_layer(Some(application), None)?;
+                profile_config.write_layer(Some(application), Some(profile))?;
+            }
+        }
         Ok(global_config
             .merge(&app_config, false)
             .merge(&profile_config, false))
@@ -101,10 +150,40 @@ impl Config {
             cfg.locale = Some(v.clone());
         }
 
+        if let Some(v) = &other.runtime {
+            cfg.runtime = Some(*v);
+        }
+
+        if let Some(v) = &other.registry {
+            cfg.registry = Some(v.clone());
+        }
+
         cfg
     }
 
     pub fn get_aspects(&self) -> Vec<Box<dyn aspects::ContainerAspect>> {
+        let mut aspects = self.get_build_aspects();
+        aspects.extend(self.get_run_aspects());
+        aspects
+    }
+
+    /// Aspects configured here that change the image, so have to be present at build time.
+    pub fn get_build_aspects(&self) -> Vec<Box<dyn aspects::ContainerAspect>> {
+        let mut aspects: Vec<Box<dyn aspects::ContainerAspect>> = Vec::new();
+
+        if let Some(timezone) = &self.timezone {
+            aspects.push(Box::new(timezone.clone()));
+        }
+
+        if let Some(locale) = &self.locale {
+            aspects.push(Box::new(locale.clone()));
+        }
+
+        aspects
+    }
+
+    /// Aspects configured here that only affect running containers.
+    pub fn get_run_aspects(&self) -> Vec<Box<dyn aspects::ContainerAspect>> {
         let mut aspects: Vec<Box<dyn aspects::ContainerAspect>> = Vec::new();
 
         if let Some(mounts) = &self.mounts {
@@ -113,10 +192,6 @@ impl Config {
             }
         }
 
-        if let Some(timezone) = &self.timezone {
-            aspects.push(Box::new(timezone.clone()));
-        }
-
         if let Some(memory) = &self.memory {
             aspects.push(Box::new(memory.clone()));
         }
@@ -129,11 +204,44 @@ impl Config {
             aspects.push(Box::new(network.clone()));
         }
 
+        aspects
+    }
+
+    /// The `key=value` settings of the build time aspects, as recorded in the image.
+    pub fn build_values(&self) -> Vec<String> {
+        let mut values = Vec::new();
+
+        if let Some(timezone) = &self.timezone {
+            values.push(format!("timezone={}", timezone.0));
+        }
+
         if let Some(locale) = &self.locale {
-            aspects.push(Box::new(locale.clone()));
+            values.push(format!("locale={}", String::from(locale)));
         }
 
-        aspects
+        values
+    }
+}
+
+/// Gets the value of an argument, treating arguments the subcommand doesn't define as not given.
+pub(crate) fn get_optional<'a, T: Clone + Send + Sync + 'static>(
+    matches: &'a ArgMatches,
+    id: &str,
+) -> Result<Option<&'a T>> {
+    match matches.try_get_one::<T>(id) {
+        Err(MatchesError::UnknownArgument { .. }) => Ok(None),
+        value => Ok(value?),
+    }
+}
+
+/// Gets the values of an argument, treating arguments the subcommand doesn't define as not given.
+pub(crate) fn get_optional_many<'a, T: Clone + Send + Sync + 'static>(
+    matches: &'a ArgMatches,
+    id: &str,
+) -> Result<Option<ValuesRef<'a, T>>> {
+    match matches.try_get_many::<T>(id) {
+        Err(MatchesError::UnknownArgument { .. }) => Ok(None),
+        values => Ok(values?),
     }
 }
 
@@ -142,8 +250,7 @@ impl TryFrom<&ArgMatches> for Config {
     fn try_from(matches: &ArgMatches) -> Result<Self> {
         let mut cfg = Config::empty();
 
-        cfg.mounts = matches
-            .get_many::<String>("mount")
+        cfg.mounts = get_optional_many::<String>(matches, "mount")?
             .map(|values_ref| {
                 values_ref
                     .map(aspects::Mount::try_from)
@@ -151,31 +258,34 @@ impl TryFrom<&ArgMatches> for Config {
             })
             .transpose()?;
 
-        cfg.timezone = matches
-            .get_one::<String>("timezone")
+        cfg.timezone = get_optional::<String>(matches, "timezone")?
             .map(aspects::Timezone::try_from)
             .transpose()?;
 
-        cfg.memory = matches
-            .get_one::<String>("memory")
+        cfg.memory = get_optional::<String>(matches, "memory")?
             .map(aspects::Memory::try_from)
             .transpose()?;
 
-        cfg.cpu_shares = matches
-            .get_one::<String>("cpu-shares")
+        cfg.cpu_shares = get_optional::<String>(matches, "cpu-shares")?
             .map(aspects::CPUShares::try_from)
             .transpose()?;
 
-        cfg.network = matches
-            .get_one::<String>("network")
+        cfg.network = get_optional::<String>(matches, "network")?
             .map(aspects::Network::try_from)
             .transpose()?;
 
-        cfg.locale = matches
-            .get_one::<String>("locale")
+        cfg.locale = get_optional::<String>(matches, "locale")?
             .map(aspects::Locale::try_from)
             .transpose()?;
 
+        cfg.runtime = get_optional::<String>(matches, "runtime")?
+            .map(runtime::Runtime::try_from)
+            .transpose()?;
+
+        cfg.registry = get_optional::<String>(matches, "registry")?
+            .map(registry::Registry::try_from)
+            .transpose()?;
+
         Ok(cfg)
     }
 }
@@ -233,9 +343,35 @@ pub fn cli_args() -> Vec<Arg> {
             .long("locale")
             .action(ArgAction::Set)
             .help("specify the locale in the form <language>_<territory>.<codeset> for the container (default: en_US.UTF8)"),
+        runtime_arg(),
+        registry_arg(),
     ]
 }
 
+/// The arguments of [`cli_args`] that matter when generating or building the image: the
+/// settings baked into it, and the runtime and registry.
+pub fn build_args() -> Vec<Arg> {
+    cli_args()
+        .into_iter()
+        .filter(|a| ["timezone", "locale", "runtime", "registry"].contains(&a.get_id().as_str()))
+        .collect()
+}
+
+pub fn runtime_arg() -> Arg {
+    Arg::new("runtime")
+        .long("runtime")
+        .action(ArgAction::Set)
+        .value_parser(["docker", "podman", "engine"])
+        .help("specify the container runtime; `engine` uses the docker socket without the CLI (default: docker)")
+}
+
+pub fn registry_arg() -> Arg {
+    Arg::new("registry")
+        .long("registry")
+        .action(ArgAction::Set)
+        .help("specify the registry app images are pushed to and pulled from, e.g. localhost:5000")
+}
+
 #[cfg(test)]
 mod merge_should {
     use super::*;
@@ -304,3 +440,45 @@ mod merge_should {
         assert_eq!(merge(&empty, &none, false), None);
     }
 }
+
+#[cfg(test)]
+mod config_should {
+    use super::*;
+    use clap::Command;
+
+    #[test]
+    fn read_only_the_args_a_subcommand_defines() -> Result<()> {
+        let matches = Command::new("build").args(cli_args()).get_matches_from([
+            "build",
+            "--timezone",
+            "America/Chicago",
+        ]);
+        let cfg = Config::try_from(&matches)?;
+        assert_eq!(
+            cfg.timezone.as_ref().map(|tz| tz.0.as_str()),
+            Some("America/Chicago")
+        );
+        assert!(get_optional::<String>(&matches, "undefined")?.is_none());
+        assert!(matches!(
+            cfg.check_profile_layer(Some("work")),
+            Err(Error::ProfileBuildConfig { .. })
+        ));
+        cfg.check_profile_layer(None)?;
+
+        let mut profile_config = cfg.clone();
+        let mut app_config = Config::empty();
+        app_config.locale = Some(aspects::Locale::try_from(&String::from("de_DE.UTF-8"))?);
+        profile_config.locale = Some(aspects::Locale::try_from(&String::from("en_US.UTF-8"))?);
+        profile_config.move_build_config(&mut app_config);
+        assert!(profile_config.build_values().is_empty());
+        assert_eq!(
+            app_config.build_values(),
+            vec!["timezone=America/Chicago", "locale=de_DE.UTF-8"]
+        );
+        assert!(matches!(
+            get_optional::<usize>(&matches, "timezone"),
+            Err(Error::MatchesError(MatchesError::Downcast { .. }))
+        ));
+        Ok(())
+    }
+}
@@ -1,27 +1,29 @@
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 
+use clap::parser::ValueSource;
 use clap::{Arg, ArgAction, ArgMatches, Command};
-use dockworker::{ContainerBuildOptions, Docker};
 use dyn_clone;
-use serde::Deserialize;
-use serde_json::from_str;
+use sha2::{Digest, Sha256};
 use tar::{Builder, Header};
 use tempfile::NamedTempFile;
 
 use super::aspects;
 use super::config;
-use super::docker;
+use super::dirs;
+use super::dockerfile::Instruction;
 use super::entrypoint;
 use super::error::{Error, Result};
+use super::export;
+use super::lockfile::{self, Lockfile};
 use super::logging;
-
-#[derive(Deserialize, Debug)]
-struct BuildOutput {
-    stream: String,
-}
+use super::packages::PackageManager;
+use super::phase::{self, Phase, Placed};
+use super::registry::Registry;
+use super::runtime;
 
 pub struct ContainerManager {
     name: String,
@@ -30,6 +32,16 @@ pub struct ContainerManager {
     aspects: Vec<Box<dyn aspects::ContainerAspect>>,
     args: Vec<String>,
     tempdir: tempfile::TempDir,
+    runtime: runtime::Runtime,
+    context_hash: String,
+    app_version: Option<String>,
+    lock: Option<Lockfile>,
+    /// Whether inputs missing from the lockfile are an error, for subcommands that build or
+    /// generate the image, rather than leaving them unpinned.
+    enforce_lock: bool,
+    registry: Option<Registry>,
+    /// Config values baked into the image, as `key=value`.
+    build_config: Vec<String>,
 }
 
 impl ContainerManager {
@@ -50,6 +62,13 @@ impl ContainerManager {
             aspects,
             args,
             tempdir,
+            runtime: runtime::Runtime::default(),
+            context_hash: String::new(),
+            app_version: None,
+            lock: None,
+            enforce_lock: false,
+            registry: None,
+            build_config: Vec::new(),
         })
     }
     pub fn default_debian(
@@ -84,12 +103,126 @@ impl ContainerManager {
         Self::default(name, tags, container_paths, aspects, args)
     }
 
+    pub fn default_fedora(
+        name: String,
+        tags: Vec<String>,
+        container_paths: Vec<String>,
+        mut aspects: Vec<Box<dyn aspects::ContainerAspect>>,
+        args: Vec<String>,
+        version: Option<String>,
+    ) -> Result<ContainerManager> {
+        let aspect = match version {
+            None => String::from("40"),
+            Some(s) => s,
+        };
+        aspects.insert(0, Box::new(Fedora { version: aspect }));
+        Self::default(name, tags, container_paths, aspects, args)
+    }
+
+    pub fn default_alpine(
+        name: String,
+        tags: Vec<String>,
+        container_paths: Vec<String>,
+        mut aspects: Vec<Box<dyn aspects::ContainerAspect>>,
+        args: Vec<String>,
+        version: Option<String>,
+    ) -> Result<ContainerManager> {
+        let aspect = match version {
+            None => String::from("3.20"),
+            Some(s) => s,
+        };
+        aspects.insert(0, Box::new(Alpine { version: aspect }));
+        Self::default(name, tags, container_paths, aspects, args)
+    }
+
+    /// Sets the app version recorded in image labels and reported by `--version`, usually the
+    /// app crate's `CARGO_PKG_VERSION`.
+    pub fn set_app_version(&mut self, version: &str) {
+        self.app_version = Some(version.to_string());
+    }
+
     fn image(&self) -> String {
         self.tags[0].clone()
     }
 
-    fn run(&self, matches: &ArgMatches) -> Result<()> {
-        let mut args: Vec<String> = vec!["--rm"].into_iter().map(String::from).collect();
+    fn container_runtime(&self) -> Result<Box<dyn runtime::ContainerRuntime>> {
+        let rt = self.runtime.connect()?;
+        log::debug!("using container runtime: {}", rt.program());
+        Ok(rt)
+    }
+
+    /// Warns if the app image is missing or was built from a different build context than the
+    /// app's current aspects generate.
+    fn check_image(&self, rt: &dyn runtime::ContainerRuntime) -> Result<()> {
+        match rt.inspect_image(&self.image())? {
+            None => log::warn!(
+                "image {} not found locally, try running `{} build` first",
+                self.image(),
+                self.name
+            ),
+            Some(image) if image.label(runtime::CONTEXT_HASH_LABEL) != Some(&self.context_hash) => {
+                log::warn!(
+                    "image {} is out of date with the app's aspects, try running `{} build`",
+                    self.image(),
+                    self.name
+                )
+            }
+            Some(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Returns the profile selected on the command line, falling back to the default profile.
+    fn profile(&self, matches: &ArgMatches) -> Result<String> {
+        Ok(config::get_optional::<String>(matches, "profile")?
+            .cloned()
+            .unwrap_or_else(|| String::from("default")))
+    }
+
+    /// Labels attached to every container dfiles starts so that `ps`, `logs`, `stop` and `attach`
+    /// can find them again.
+    fn labels(&self, matches: &ArgMatches) -> Result<Vec<String>> {
+        Ok(vec![
+            format!("{}={}", runtime::APP_LABEL, self.name),
+            format!("{}={}", runtime::PROFILE_LABEL, self.profile(matches)?),
+        ])
+    }
+
+    fn label_args(&self, matches: &ArgMatches) -> Result<Vec<String>> {
+        Ok(self
+            .labels(matches)?
+            .into_iter()
+            .flat_map(|l| vec![String::from("--label"), l])
+            .collect())
+    }
+
+    fn run(&self, matches: &ArgMatches) -> Result<i32> {
+        let mut app_args = self.args.clone();
+        if let Some(values) = matches.try_get_many::<String>("args")? {
+            app_args.extend(values.cloned());
+        }
+
+        let rt = self.container_runtime()?;
+        let running = match self.running_container(rt.as_ref(), matches) {
+            // a dry run shouldn't need a reachable runtime
+            Err(e) if matches.get_flag("dry-run") => {
+                log::warn!("unable to check for a running container: {}", e);
+                None
+            }
+            result => result?,
+        };
+        if let Some(container) = running {
+            return self.forward(rt.as_ref(), matches, &container, app_args);
+        }
+
+        let mut args: Vec<String> = vec!["--rm", "--init"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        if matches.get_flag("detach") {
+            args.push(String::from("--detach"));
+        }
+        args.extend(self.label_args(matches)?);
 
         log::debug!("active dfiles aspects:");
         for aspect in &self.aspects {
@@ -97,16 +230,65 @@ impl ContainerManager {
             args.extend(aspect.run_args(Some(matches))?);
         }
 
-        let ep_args = entrypoint::setup(self.tempdir.path(), &self.aspects)?;
-        args.extend(ep_args);
+        let ep = entrypoint::setup(
+            &self.entrypoint_dir(matches)?,
+            &self.aspects,
+            self.package_manager(),
+        )?;
+        args.extend(ep.run_args);
         args.push(self.image());
-        args.extend_from_slice(&self.args);
-        docker::run(args);
-        Ok(())
+        args.extend(app_args);
+
+        self.launch(rt.as_ref(), matches, args, ep.script)
     }
 
-    fn cmd(&self, matches: &ArgMatches) -> Result<()> {
-        let mut args: Vec<String> = vec!["-it", "--rm"].into_iter().map(String::from).collect();
+    /// Directory `run` writes the entrypoint script to. A detached container outlives this process
+    /// and its tempdir, so its script is kept in the app's data directory instead, unless nothing
+    /// is actually run.
+    fn entrypoint_dir(&self, matches: &ArgMatches) -> Result<PathBuf> {
+        if !matches.get_flag("detach") || matches.get_flag("dry-run") {
+            return Ok(self.tempdir.path().to_path_buf());
+        }
+        let profile = matches
+            .try_get_one::<String>("profile")?
+            .map_or("default", String::as_str);
+        Ok(dirs::get_data_dir(Some(&self.name), None)?
+            .join("entrypoints")
+            .join(profile))
+    }
+
+    /// Runs the app command in a container that is already running for the selected profile, so
+    /// that a second launch opens a new window or URL in the existing instance.
+    fn forward(
+        &self,
+        rt: &dyn runtime::ContainerRuntime,
+        matches: &ArgMatches,
+        container: &runtime::ContainerSummary,
+        app_args: Vec<String>,
+    ) -> Result<i32> {
+        let user = container
+            .labels
+            .get(runtime::USER_LABEL)
+            .map(String::as_str);
+
+        if matches.get_flag("dry-run") {
+            println!(
+                "{}",
+                runtime::command_line(rt.program(), &rt.exec_argv(&container.name, user, app_args))
+            );
+            return Ok(0);
+        }
+
+        log::info!("{} is already running, forwarding to it", container.name);
+        rt.exec(&container.name, user, app_args)
+    }
+
+    fn cmd(&self, matches: &ArgMatches) -> Result<i32> {
+        let mut args: Vec<String> = vec!["-it", "--rm", "--init"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        args.extend(self.label_args(matches)?);
 
         log::debug!("active dfiles aspects:");
         for aspect in &self.aspects {
@@ -120,74 +302,758 @@ impl ContainerManager {
                 values_ref.map(String::from).collect()
             });
 
-        let ep_args = entrypoint::setup(self.tempdir.path(), &self.aspects)?;
-        args.extend(ep_args);
+        let ep = entrypoint::setup(self.tempdir.path(), &self.aspects, self.package_manager())?;
+        args.extend(ep.run_args);
         args.push(self.image());
         args.extend_from_slice(command.as_slice());
 
-        docker::run(args);
+        let rt = self.container_runtime()?;
+        self.launch(rt.as_ref(), matches, args, ep.script)
+    }
+
+    /// Hands the assembled `run` arguments to the container runtime or, with `--dry-run`, prints
+    /// the exact invocation and entrypoint script instead.
+    fn launch(
+        &self,
+        rt: &dyn runtime::ContainerRuntime,
+        matches: &ArgMatches,
+        args: Vec<String>,
+        entrypoint_script: Option<String>,
+    ) -> Result<i32> {
+        if matches.get_flag("dry-run") {
+            println!(
+                "{}",
+                runtime::command_line(rt.program(), &rt.run_argv(args))
+            );
+            if let Some(script) = entrypoint_script {
+                println!("\n# /{}", entrypoint::ENTRYPOINT_SETUP_SCRIPT);
+                println!("{}", script);
+            }
+            return Ok(0);
+        }
+
+        if matches.get_flag("pull") && rt.inspect_image(&self.image())?.is_none() {
+            self.pull_image(rt, &self.image())?;
+        }
+        self.check_image(rt)?;
+        rt.run(args)
+    }
+
+    /// Returns the container name given with `--name` or generated by the `Name` aspect, if any.
+    fn container_name(&self, matches: &ArgMatches) -> Result<Option<String>> {
+        if let Some(name) = config::get_optional::<String>(matches, "container_name")? {
+            return Ok(Some(name.clone()));
+        }
+        for aspect in self.aspects.iter().filter(|a| a.name() == "Name") {
+            if let [_, name] = aspect.run_args(Some(matches))?.as_slice() {
+                return Ok(Some(name.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the running container for the selected app and profile, if any. Containers are
+    /// matched by name when one is known and by dfiles labels otherwise.
+    fn running_container(
+        &self,
+        rt: &dyn runtime::ContainerRuntime,
+        matches: &ArgMatches,
+    ) -> Result<Option<runtime::ContainerSummary>> {
+        let name = self.container_name(matches)?;
+        let labels = match name {
+            Some(_) => Vec::new(),
+            None => self.labels(matches)?,
+        };
+        Ok(rt
+            .list_containers(&labels)?
+            .into_iter()
+            .find(|c| c.is_running() && name.as_ref().is_none_or(|n| &c.name == n)))
+    }
+
+    /// Finds the container that `logs`, `stop` and `attach` should act on: the one given with
+    /// `--name`, otherwise the app's container for the selected profile, preferring a running one
+    /// and falling back to the name generated by the `Name` aspect for containers started without
+    /// dfiles labels.
+    fn find_container(
+        &self,
+        rt: &dyn runtime::ContainerRuntime,
+        matches: &ArgMatches,
+    ) -> Result<String> {
+        if let Some(name) = config::get_optional::<String>(matches, "container_name")? {
+            return Ok(name.clone());
+        }
+
+        let mut containers = rt.list_containers(&self.labels(matches)?)?;
+        containers.sort_by_key(|c| !c.is_running());
+        if let Some(c) = containers.first() {
+            return Ok(c.name.clone());
+        }
+
+        if let Some(name) = self.container_name(matches)? {
+            if rt.list_containers(&[])?.iter().any(|c| c.name == name) {
+                return Ok(name);
+            }
+        }
+
+        Err(Error::NoContainerFound {
+            app: self.name.clone(),
+            profile: self.profile(matches)?,
+        })
+    }
+
+    /// Lists the app's containers across all profiles.
+    fn ps(&self) -> Result<()> {
+        let rt = self.container_runtime()?;
+        let containers = rt.list_containers(&[format!("{}={}", runtime::APP_LABEL, self.name)])?;
+
+        println!(
+            "{:<24} {:<16} {:<10} {:<24} IMAGE",
+            "NAME", "PROFILE", "STATE", "STATUS"
+        );
+        for c in containers {
+            let profile = c
+                .labels
+                .get(runtime::PROFILE_LABEL)
+                .map(String::as_str)
+                .unwrap_or("");
+            println!(
+                "{:<24} {:<16} {:<10} {:<24} {}",
+                c.name, profile, c.state, c.status, c.image
+            );
+        }
         Ok(())
     }
 
-    fn build(&self) -> Result<()> {
+    fn logs(&self, matches: &ArgMatches) -> Result<i32> {
+        let rt = self.container_runtime()?;
+        let container = self.find_container(rt.as_ref(), matches)?;
+        rt.logs(&container, matches.get_flag("follow"))
+    }
+
+    fn stop(&self, matches: &ArgMatches) -> Result<()> {
+        let rt = self.container_runtime()?;
+        let container = self.find_container(rt.as_ref(), matches)?;
+        rt.stop(&container)
+    }
+
+    fn attach(&self, matches: &ArgMatches) -> Result<i32> {
+        let rt = self.container_runtime()?;
+        let container = self.find_container(rt.as_ref(), matches)?;
+        rt.attach(&container)
+    }
+
+    fn build(&self, matches: &ArgMatches) -> Result<()> {
         let mut tar_file = NamedTempFile::new()?;
         self.generate_archive_impl(tar_file.as_file_mut())?;
 
-        let docker = Docker::connect_with_defaults()?;
-        let options = ContainerBuildOptions {
+        let mut tags = self.tags.clone();
+        if let Some(values) = matches.try_get_many::<String>("tag")? {
+            tags.extend(values.cloned());
+        }
+        let build_args = match matches.try_get_many::<String>("build-arg")? {
+            Some(values) => values.map(parse_build_arg).collect::<Result<_>>()?,
+            None => Vec::new(),
+        };
+
+        let options = runtime::BuildOptions {
             dockerfile: "Dockerfile".into(),
-            t: self.tags.clone(),
-            ..ContainerBuildOptions::default()
+            tags,
+            no_cache: matches.get_flag("no-cache"),
+            pull: matches.get_flag("pull"),
+            build_args,
+            quiet: matches.get_flag("quiet"),
+            labels: vec![(
+                runtime::CONTEXT_HASH_LABEL.to_string(),
+                self.context_hash.clone(),
+            )],
         };
 
-        let res = docker.build_image(options, tar_file.path())?;
-        BufReader::new(res)
-            .lines()
-            .filter_map(std::result::Result::ok)
-            .map(|l| from_str::<BuildOutput>(&l))
-            .filter_map(std::result::Result::ok)
-            .for_each(|bo: BuildOutput| print!("{}", bo.stream));
+        let rt = self.container_runtime()?;
+        if matches.get_flag("locked") {
+            let lock = self.lock.as_ref().ok_or(Error::MissingLockfile)?;
+            let drift = lock.drift(&self.resolve_inputs(rt.as_ref())?);
+            if !drift.is_empty() {
+                return Err(Error::LockfileDrift(drift.join("\n")));
+            }
+        } else if self.lock.is_none() {
+            // downloads pinned with the `Download` aspect carry their own checksum
+            for url in self.inputs().1 {
+                log::warn!(
+                    "{} is downloaded without a checksum; run `lock` to pin it",
+                    url
+                );
+            }
+        }
+
+        let rebuild = matches.get_flag("force")
+            || options.no_cache
+            || options.pull
+            || !options.build_args.is_empty()
+            || options.tags.len() > self.tags.len();
+        if !rebuild {
+            if let Some(image) = rt.inspect_image(&self.image())? {
+                if image.label(runtime::CONTEXT_HASH_LABEL) == Some(&self.context_hash) {
+                    println!(
+                        "image {} is up to date, use --force to rebuild it anyway",
+                        self.image()
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        let id = rt.build(&options, tar_file.path())?;
+        if options.quiet {
+            println!("{}", id);
+        } else {
+            println!("built image {}", id);
+        }
         Ok(())
     }
 
-    fn generate_archive_impl(&self, f: &mut std::fs::File) -> Result<()> {
-        let mut a = Builder::new(f);
+    /// Reference of a local image tag in the configured registry, or the tag itself when no
+    /// registry is configured.
+    fn remote(&self, tag: &str) -> String {
+        match &self.registry {
+            Some(registry) => registry.reference(tag),
+            None => tag.to_string(),
+        }
+    }
+
+    /// Pushes the locally built app image to the registry under each of the app's tags.
+    fn push(&self) -> Result<()> {
+        let rt = self.container_runtime()?;
+        if rt.inspect_image(&self.image())?.is_none() {
+            return Err(Error::ImageNotFound(self.image()));
+        }
+        for tag in &self.tags {
+            let remote = self.remote(tag);
+            if remote != *tag {
+                rt.tag(tag, &remote)?;
+            }
+            rt.push(&remote)?;
+            println!("pushed {}", remote);
+        }
+        Ok(())
+    }
+
+    /// Pulls the app image from the registry, tagging it with the local name `run` expects.
+    fn pull_image(&self, rt: &dyn runtime::ContainerRuntime, tag: &str) -> Result<()> {
+        let remote = self.remote(tag);
+        rt.pull(&remote)?;
+        if remote != tag {
+            rt.tag(&remote, tag)?;
+        }
+        println!("pulled {}", remote);
+        Ok(())
+    }
+
+    fn pull(&self) -> Result<()> {
+        let rt = self.container_runtime()?;
+        for tag in &self.tags {
+            self.pull_image(rt.as_ref(), tag)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the Dockerfile snippets of every aspect, along with the name of the aspect, in the
+    /// order they appear in the Dockerfile.
+    fn dockerfile_snippets(&self) -> Result<Vec<Placed<aspects::DockerfileSnippet>>> {
+        let mut placed = Vec::new();
+        let mut base_installs = Vec::new();
+
+        // package installs go at the start of their phase, except that base packages can only be
+        // installed once the base image is in place
+        let package_manager = self.package_manager();
+        for (phase, names) in self.packages() {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            let every_aspect = vec![String::from(phase::EVERY_ASPECT)];
+            let (after, before) = match phase {
+                Phase::Base => (every_aspect, Vec::new()),
+                _ => (Vec::new(), every_aspect),
+            };
+            let install = Placed {
+                aspect: String::from("Packages"),
+                key: None,
+                phase,
+                after,
+                before,
+                item: aspects::DockerfileSnippet {
+                    phase,
+                    instructions: vec![package_manager.install(&names)],
+                    ..Default::default()
+                },
+            };
+            if phase == Phase::Base {
+                base_installs.push(install);
+            } else {
+                placed.push(install);
+            }
+        }
 
-        let mut contents: BTreeMap<u8, String> = BTreeMap::new();
         for aspect in &self.aspects {
-            let dockerfile_snippets = aspect.dockerfile_snippets();
-            for snippet in dockerfile_snippets {
-                contents
-                    .entry(snippet.order)
-                    .and_modify(|e| {
-                        e.push('\n');
-                        e.push_str(snippet.content.as_str());
+            for mut snippet in aspect.dockerfile_snippets_for(package_manager) {
+                placed.push(Placed {
+                    aspect: aspect.name(),
+                    key: snippet.key.clone(),
+                    phase: snippet.phase,
+                    after: std::mem::take(&mut snippet.after),
+                    before: std::mem::take(&mut snippet.before),
+                    item: snippet,
+                });
+            }
+        }
+        placed.append(&mut base_installs);
+        placed.push(Placed {
+            aspect: String::from("dfiles"),
+            key: None,
+            phase: Phase::Finalize,
+            after: Vec::new(),
+            before: Vec::new(),
+            item: aspects::DockerfileSnippet {
+                phase: Phase::Finalize,
+                instructions: vec![self.label_instruction()],
+                ..Default::default()
+            },
+        });
+        let mut arranged = phase::arrange(placed)?;
+        if let Some(lock) = &self.lock {
+            for placed in &mut arranged {
+                placed.item.instructions = placed
+                    .item
+                    .instructions
+                    .iter()
+                    .map(|i| match lock.pin(i) {
+                        // the image is then reported as out of date wherever it's checked
+                        Err(Error::UnlockedInput(input)) if !self.enforce_lock => {
+                            log::warn!(
+                                "{} is not in the lockfile, run the `lock` subcommand again",
+                                input
+                            );
+                            Ok(i.clone())
+                        }
+                        pinned => pinned,
                     })
-                    .or_insert(snippet.content);
+                    .collect::<Result<_>>()?;
             }
-            for file in aspect.container_files() {
-                add_file_to_archive(&mut a, &file.container_path, &file.contents)?;
+        }
+        Ok(arranged)
+    }
+
+    /// Returns the base images and download URLs the aspects build from, before any pinning.
+    fn inputs(&self) -> (Vec<String>, Vec<String>) {
+        let mut images = Vec::new();
+        let mut urls = Vec::new();
+        let package_manager = self.package_manager();
+        for snippet in self
+            .aspects
+            .iter()
+            .flat_map(|a| a.dockerfile_snippets_for(package_manager))
+        {
+            for instruction in snippet.instructions {
+                match instruction {
+                    Instruction::From(image) if !image.contains('@') => images.push(image),
+                    Instruction::Add { src, .. } if lockfile::is_url(&src) => urls.push(src),
+                    _ => {}
+                }
+            }
+        }
+        (images, urls)
+    }
+
+    /// Resolves the current digest of every base image, pulling it first, and the checksum of
+    /// every download.
+    fn resolve_inputs(&self, rt: &dyn runtime::ContainerRuntime) -> Result<Lockfile> {
+        let (images, urls) = self.inputs();
+        let mut resolved = Lockfile::default();
+        for image in images {
+            rt.pull(&image)?;
+            let digest = rt
+                .inspect_image(&image)?
+                .and_then(|info| info.repo_digests)
+                .into_iter()
+                .flatten()
+                .find_map(|d| d.split_once('@').map(|(_, digest)| digest.to_string()))
+                .ok_or_else(|| Error::MissingImageDigest(image.clone()))?;
+            resolved.images.insert(image, digest);
+        }
+        for url in urls {
+            let sha256 = lockfile::sha256_of_url(&url)?;
+            resolved.downloads.insert(url, sha256);
+        }
+        Ok(resolved)
+    }
+
+    /// Resolves the app's build inputs and records them in its lockfile.
+    fn lock(&self) -> Result<()> {
+        let rt = self.container_runtime()?;
+        let resolved = self.resolve_inputs(rt.as_ref())?;
+        resolved.save(&self.name)?;
+        for (image, digest) in &resolved.images {
+            println!("{}  {}", image, digest);
+        }
+        for (url, sha256) in &resolved.downloads {
+            println!("{}  sha256:{}", url, sha256);
+        }
+        Ok(())
+    }
+
+    /// The package manager of the base image, apt unless an aspect says otherwise.
+    fn package_manager(&self) -> PackageManager {
+        self.aspects
+            .iter()
+            .find_map(|a| a.package_manager())
+            .unwrap_or_default()
+    }
+
+    /// Merges the packages asked for by every aspect into one list per phase, installing each
+    /// package only in the earliest phase it is needed. Package names are translated for the
+    /// base image, unless given as native names, and packages it doesn't have are left out.
+    fn packages(&self) -> BTreeMap<Phase, Vec<String>> {
+        let package_manager = self.package_manager();
+        let requested: Vec<(Phase, String)> = self
+            .aspects
+            .iter()
+            .flat_map(|a| a.packages())
+            .flat_map(|p| {
+                let (phase, native) = (p.phase, p.native);
+                p.names.into_iter().map(move |n| (phase, native, n))
+            })
+            .filter_map(
+                |(phase, native, name)| match package_manager.package_name(&name) {
+                    _ if native => Some((phase, name)),
+                    Some(translated) => Some((phase, translated.to_string())),
+                    None => {
+                        log::debug!("package {} is not available, skipping it", name);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        let mut earliest: BTreeMap<&str, Phase> = BTreeMap::new();
+        for (phase, name) in &requested {
+            let entry = earliest.entry(name.as_str()).or_insert(*phase);
+            *entry = (*entry).min(*phase);
+        }
+
+        let mut packages: BTreeMap<Phase, Vec<String>> = BTreeMap::new();
+        for (phase, name) in &requested {
+            let names = packages.entry(*phase).or_default();
+            if earliest[name.as_str()] == *phase && !names.contains(name) {
+                names.push(name.clone());
             }
         }
+        packages.retain(|_, names| !names.is_empty());
+        packages
+    }
 
+    /// Returns the files making up the build context: every aspect's container files followed by
+    /// the generated Dockerfile.
+    fn context_files(&self) -> Result<Vec<aspects::ContainerFile>> {
+        let mut files: Vec<aspects::ContainerFile> = self
+            .aspects
+            .iter()
+            .flat_map(|a| a.container_files())
+            .collect();
+
+        // each block is headed by the aspect and phase it came from
         let mut dockerfile_contents = String::new();
+        for placed in self.dockerfile_snippets()? {
+            dockerfile_contents.push_str(&format!(
+                "# {} ({})\n{}\n\n",
+                placed.aspect,
+                placed.phase,
+                placed.item.render()
+            ));
+        }
+
+        files.push(aspects::ContainerFile {
+            container_path: String::from("Dockerfile"),
+            contents: dockerfile_contents.into_bytes(),
+        });
+        Ok(files)
+    }
 
-        for content in contents.values() {
-            dockerfile_contents.push_str(content.as_str());
-            dockerfile_contents.push('\n');
-            dockerfile_contents.push('\n');
+    /// Dockerfile `LABEL` instruction recording which app, versions, aspects and config values an
+    /// image was built from. The base image label is added by the distro aspects.
+    fn label_instruction(&self) -> Instruction {
+        let aspects: Vec<String> = self.aspects.iter().map(|a| a.name()).collect();
+        let mut labels = vec![
+            (runtime::TITLE_LABEL, self.name.clone()),
+            (runtime::APP_LABEL, self.name.clone()),
+            (
+                runtime::DFILES_VERSION_LABEL,
+                env!("CARGO_PKG_VERSION").to_string(),
+            ),
+            (runtime::ASPECTS_LABEL, aspects.join(",")),
+        ];
+        if let Some(version) = &self.app_version {
+            labels.insert(1, (runtime::VERSION_LABEL, version.clone()));
+        }
+        if !self.build_config.is_empty() {
+            labels.push((runtime::CONFIG_LABEL, self.build_config.join(",")));
         }
+        Instruction::label(&labels)
+    }
 
-        add_file_to_archive(&mut a, "Dockerfile", dockerfile_contents.as_bytes())?;
+    /// Prints the dfiles metadata recorded in the local app image.
+    fn inspect(&self) -> Result<()> {
+        let rt = self.container_runtime()?;
+        let image = rt
+            .inspect_image(&self.image())?
+            .ok_or_else(|| Error::ImageNotFound(self.image()))?;
 
+        println!("{:<32} {}", "image", self.image());
+        println!("{:<32} {}", "id", image.id);
+        for key in runtime::IMAGE_LABELS {
+            let value = image.label(key).unwrap_or("-");
+            if key == runtime::ASPECTS_LABEL || key == runtime::CONFIG_LABEL {
+                for (i, item) in value.split(',').enumerate() {
+                    println!("{:<32} {}", if i == 0 { key } else { "" }, item);
+                }
+            } else {
+                println!("{:<32} {}", key, value);
+            }
+        }
         Ok(())
     }
 
-    fn generate_archive(&self) -> Result<()> {
-        let mut tar_file = File::create(format!("{}.tar", self.name))?;
+    /// Removes the app's local images that are superseded by its current tags, keeping the
+    /// `--keep` most recent ones and any used by a running container of the app.
+    fn prune(&self, matches: &ArgMatches) -> Result<()> {
+        let rt = self.container_runtime()?;
+        let keep = matches
+            .get_one::<usize>("keep")
+            .copied()
+            .unwrap_or_default();
+        let dry_run = matches.get_flag("dry-run");
+
+        let mut repositories: Vec<&str> =
+            self.tags.iter().map(|t| runtime::split_tag(t).0).collect();
+        repositories.sort_unstable();
+        repositories.dedup();
+        // the label also finds images left untagged by a rebuild, the repositories find images
+        // built before images were labelled with their app
+        let mut filters = vec![format!("label={}={}", runtime::APP_LABEL, self.name)];
+        filters.extend(repositories.iter().map(|r| format!("reference={}", r)));
+        let mut images: Vec<runtime::ImageSummary> = Vec::new();
+        for filter in filters {
+            for image in rt.list_images(&[filter])? {
+                if !images.iter().any(|i| i.id == image.id) {
+                    images.push(image);
+                }
+            }
+        }
+        let containers = rt.list_containers(&[format!("{}={}", runtime::APP_LABEL, self.name)])?;
+
+        for (image, pruning) in plan_prune(images, &self.tags, keep, &containers) {
+            // only the app's own tags are removed; untagged images are removed by ID
+            let mut references: Vec<String> = image
+                .tags
+                .iter()
+                .filter(|t| repositories.contains(&runtime::split_tag(t).0))
+                .cloned()
+                .collect();
+            if references.is_empty() {
+                references.push(image.id.clone());
+            }
+            let name = references.join(", ");
+            match pruning {
+                Pruning::Current => println!("keeping {} (current)", name),
+                Pruning::Kept => println!("keeping {}", name),
+                Pruning::InUse(container) => {
+                    println!("keeping {} (used by running container {})", name, container)
+                }
+                Pruning::Remove if dry_run => println!("would remove {}", name),
+                Pruning::Remove => {
+                    // e.g. an image still used by a stopped container
+                    match references.iter().try_for_each(|r| rt.remove_image(r)) {
+                        Ok(()) => println!("removed {}", name),
+                        Err(e) => println!("skipping {} ({})", name, e),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the app image with its tags to a tarball, `<name>-image.tar` by default, along with
+    /// a manifest of its dfiles labels and aspects, and writes the tarball's checksum next to it.
+    fn export(&self, matches: &ArgMatches) -> Result<()> {
+        let rt = self.container_runtime()?;
+        let image = rt
+            .inspect_image(&self.image())?
+            .ok_or_else(|| Error::ImageNotFound(self.image()))?;
+
+        // the saved image can be large, so it is removed as soon as the export is written
+        let scratch = tempfile::Builder::new()
+            .prefix(&format!("dfiles-{}-export-", self.name))
+            .tempdir()?;
+        let saved = scratch.path().join("saved.tar");
+        rt.save(&self.tags, &saved)?;
+
+        let labels: BTreeMap<String, String> = runtime::IMAGE_LABELS
+            .iter()
+            .filter_map(|key| image.label(key).map(|v| (key.to_string(), v.to_string())))
+            .collect();
+        let manifest = export::Manifest {
+            app: self.name.clone(),
+            tags: self.tags.clone(),
+            image_id: image.id.clone(),
+            aspects: image
+                .label(runtime::ASPECTS_LABEL)
+                .unwrap_or_default()
+                .split(',')
+                .filter(|a| !a.is_empty())
+                .map(String::from)
+                .collect(),
+            labels,
+            image_sha256: export::sha256_file(&saved)?,
+        };
+
+        let output = matches
+            .get_one::<PathBuf>("output")
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from(format!("{}-image.tar", self.name)));
+        export::write(&output, &manifest, &saved)?;
+        let sha256 = export::write_checksum(&output)?;
+        println!("exported {} to {}", self.tags.join(", "), output.display());
+        println!("sha256 {}", sha256);
+        Ok(())
+    }
+
+    /// Loads an app image exported with `export`, after checking the tarball against the
+    /// expected checksum and the image archive against the manifest.
+    fn import(&self, matches: &ArgMatches) -> Result<()> {
+        let input = matches
+            .get_one::<PathBuf>("input")
+            .ok_or_else(|| Error::InvalidExport(String::new()))?;
+        let expected = match matches.get_one::<String>("sha256") {
+            Some(sha256) => Some(sha256.clone()),
+            None => export::read_checksum(input)?,
+        };
+        match expected {
+            Some(sha256) => export::verify(input, &sha256)?,
+            None => log::warn!(
+                "no checksum given and {} not found, skipping the tarball checksum check",
+                export::checksum_path(input).display()
+            ),
+        }
+
+        let scratch = tempfile::Builder::new()
+            .prefix(&format!("dfiles-{}-import-", self.name))
+            .tempdir()?;
+        let (manifest, image) = export::read(input, scratch.path())?;
+        if manifest.app != self.name {
+            return Err(Error::ExportForOtherApp {
+                app: manifest.app,
+                expected: self.name.clone(),
+            });
+        }
+
+        let rt = self.container_runtime()?;
+        rt.load(&image)?;
+        println!(
+            "imported {} ({})",
+            manifest.tags.join(", "),
+            manifest.image_id
+        );
+        println!("aspects {}", manifest.aspects.join(", "));
+        if manifest.labels.get(runtime::CONTEXT_HASH_LABEL) != Some(&self.context_hash) {
+            log::warn!(
+                "the imported image was built from different aspects than this version of {} generates",
+                self.name
+            );
+        }
+        Ok(())
+    }
+
+    fn generate_archive_impl(&self, f: &mut std::fs::File) -> Result<()> {
+        let mut a = Builder::new(f);
+        for file in self.context_files()? {
+            add_file_to_archive(&mut a, &file.container_path, &file.contents)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the build context as a tar archive, `<name>.tar` by default, or with `--dir` as an
+    /// unpacked directory.
+    fn generate_archive(&self, matches: &ArgMatches) -> Result<()> {
+        if let Some(dir) = matches.get_one::<PathBuf>("dir") {
+            return write_context_dir(&self.context_files()?, dir);
+        }
+
+        let output = matches
+            .get_one::<PathBuf>("output")
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from(format!("{}.tar", self.name)));
+        let mut tar_file = File::create(output)?;
         self.generate_archive_impl(&mut tar_file)
     }
 
+    /// Lists what every active aspect contributes at build time and at run time, along with the
+    /// phase and ordering constraints of each Dockerfile and entrypoint snippet.
+    fn explain(&self, matches: &ArgMatches) -> Result<()> {
+        // surface ordering errors before listing anything
+        self.dockerfile_snippets()?;
+        let package_manager = self.package_manager();
+        entrypoint::arrange(&self.aspects, package_manager)?;
+
+        for aspect in &self.aspects {
+            println!("{}", aspect.name());
+            for snippet in aspect.dockerfile_snippets_for(package_manager) {
+                let rendered = snippet.render();
+                let first_line = rendered
+                    .lines()
+                    .find(|l| !l.trim().is_empty())
+                    .unwrap_or_default();
+                println!(
+                    "  build  dockerfile  {:<15}  {}{}",
+                    snippet.phase,
+                    first_line,
+                    constraints(&snippet.after, &snippet.before)
+                );
+            }
+            for packages in aspect.packages() {
+                println!(
+                    "  build  packages    {:<15}  {}",
+                    packages.phase,
+                    packages.names.join(" ")
+                );
+            }
+            for file in aspect.container_files() {
+                println!("  build  file        {:<15}  {}", "", file.container_path);
+            }
+            for snippet in aspect.entrypoint_snippets_for(package_manager)? {
+                let first_line = snippet.description.lines().next().unwrap_or_default();
+                println!(
+                    "  run    entrypoint  {:<15}  {}{}",
+                    snippet.phase,
+                    first_line,
+                    constraints(&snippet.after, &snippet.before)
+                );
+            }
+            let args = aspect.run_args(Some(matches))?;
+            if !args.is_empty() {
+                println!("  run    args        {:<15}  {}", "", args.join(" "));
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the generated Dockerfile.
+    fn dockerfile(&self) -> Result<()> {
+        if let Some(dockerfile) = self
+            .context_files()?
+            .iter()
+            .find(|f| f.container_path == "Dockerfile")
+        {
+            print!("{}", String::from_utf8_lossy(&dockerfile.contents));
+        }
+        Ok(())
+    }
+
     /// Takes configuration options for the dfiles binary and saves them to be loaded at build or
     /// run time.
     ///
@@ -202,36 +1068,233 @@ impl ContainerManager {
     fn config(&self, matches: &ArgMatches) -> Result<()> {
         let cfg = config::Config::try_from(matches)?;
 
-        let profile: Option<&str> = matches
-            .try_get_one::<String>("profile")?
-            .map(|x| x.as_str());
+        // config applies to all of the app's profiles unless a profile is given explicitly
+        let profile: Option<&str> = match matches.value_source("profile") {
+            Some(ValueSource::CommandLine) => matches
+                .try_get_one::<String>("profile")?
+                .map(|x| x.as_str()),
+            _ => None,
+        };
 
         cfg.save(Some(&self.name), profile)
     }
 
-    fn load_config(&mut self, matches: &ArgMatches) -> Result<()> {
-        let profile: Option<&str> = matches
-            .try_get_one::<String>("profile")?
-            .map(|x| x.as_str());
+    /// Merges the global, app and profile config layers with the config given on the command
+    /// line.
+    fn merged_config(&self, matches: &ArgMatches) -> Result<config::Config> {
+        let profile: Option<&str> =
+            config::get_optional::<String>(matches, "profile")?.map(|x| x.as_str());
 
         let cfg = config::Config::load(&self.name, profile)?;
 
         let cli_cfg = config::Config::try_from(matches)?;
 
-        self.aspects
-            .extend(cfg.merge(&cli_cfg, false).get_aspects());
+        Ok(cfg.merge(&cli_cfg, false))
+    }
+
+    /// Adds the configured aspects that change the image and hashes the resulting build context;
+    /// run time aspects are left out as they don't affect the image.
+    fn add_build_config(&mut self, merged: &config::Config) -> Result<()> {
+        self.aspects.extend(merged.get_build_aspects());
+        self.build_config = merged.build_values();
+        self.context_hash = context_hash(&self.context_files()?);
         Ok(())
     }
 
-    pub fn execute(&mut self) -> Result<()> {
+    /// Loads the config for subcommands that generate the build context.
+    fn load_build_config(&mut self, matches: &ArgMatches) -> Result<()> {
+        let merged = self.merged_config(matches)?;
+        self.add_build_config(&merged)
+    }
+
+    fn load_config(&mut self, matches: &ArgMatches) -> Result<()> {
+        let merged = self.merged_config(matches)?;
+        if let Some(r) = merged.runtime {
+            self.runtime = r;
+        }
+        self.registry = merged.registry.clone();
+        self.add_build_config(&merged)?;
+        self.aspects.extend(merged.get_run_aspects());
+        Ok(())
+    }
+
+    /// Selects the container runtime and registry for subcommands that don't otherwise load the
+    /// layered config.
+    fn load_runtime(&mut self, matches: &ArgMatches) -> Result<()> {
+        let cfg = config::Config::load(&self.name, None)?;
+
+        let cli_runtime = matches
+            .try_get_one::<String>("runtime")?
+            .map(runtime::Runtime::try_from)
+            .transpose()?;
+
+        if let Some(r) = cli_runtime.or(cfg.runtime) {
+            self.runtime = r;
+        }
+
+        let cli_registry = config::get_optional::<String>(matches, "registry")?
+            .map(Registry::try_from)
+            .transpose()?;
+        self.registry = cli_registry.or(cfg.registry);
+        Ok(())
+    }
+
+    /// Parses the command line and runs the requested subcommand, returning the exit code the app
+    /// binary should exit with. For `run` and `cmd` that is the exit code of the container.
+    pub fn execute(&mut self) -> Result<i32> {
         let mut run = Command::new("run").about("run app in container");
         let mut cmd = Command::new("cmd").about("run specified command in container");
-        let mut build = Command::new("build").about("build app container");
+        let mut build = Command::new("build")
+            .about("build app container")
+            .arg(
+                Arg::new("no-cache")
+                    .long("no-cache")
+                    .action(ArgAction::SetTrue)
+                    .help("do not use cached layers, e.g. to pick up the latest app package"),
+            )
+            .arg(
+                Arg::new("pull")
+                    .long("pull")
+                    .action(ArgAction::SetTrue)
+                    .help("always pull a newer version of the base image"),
+            )
+            .arg(
+                Arg::new("build-arg")
+                    .long("build-arg")
+                    .value_name("KEY=VAL")
+                    .action(ArgAction::Append)
+                    .help("set a build-time variable"),
+            )
+            .arg(
+                Arg::new("tag")
+                    .short('t')
+                    .long("tag")
+                    .action(ArgAction::Append)
+                    .help("additional name:tag for the built image"),
+            )
+            .arg(
+                Arg::new("quiet")
+                    .short('q')
+                    .long("quiet")
+                    .action(ArgAction::SetTrue)
+                    .help("suppress build output and print only the image ID"),
+            )
+            .arg(
+                Arg::new("force")
+                    .long("force")
+                    .action(ArgAction::SetTrue)
+                    .help("build even if the image is up to date with the app's aspects"),
+            )
+            .arg(
+                Arg::new("locked")
+                    .long("locked")
+                    .action(ArgAction::SetTrue)
+                    .help(
+                        "refuse to build if the base image or downloads differ from the lockfile",
+                    ),
+            );
+        let lock = Command::new("lock")
+            .about("pin the base image digest and download checksums in the app's lockfile")
+            .arg(config::runtime_arg());
         let mut config = Command::new("config").about("configure app container settings");
-        let generate_archive =
-            Command::new("generate-archive").about("generate archive used to build container");
+        let mut generate_archive = Command::new("generate-archive")
+            .about("generate archive used to build container")
+            .arg(
+                Arg::new("output")
+                    .short('o')
+                    .long("output")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("path of the archive to write (default: <app>.tar)"),
+            )
+            .arg(
+                Arg::new("dir")
+                    .long("dir")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .conflicts_with("output")
+                    .help("write the build context unpacked into this directory instead"),
+            );
+        let mut dockerfile = Command::new("dockerfile").about("print the generated Dockerfile");
+        let mut explain = Command::new("explain")
+            .about("list what each aspect contributes to the image and to running containers");
+        let ps = Command::new("ps")
+            .about("list app containers")
+            .arg(config::runtime_arg());
+        let mut logs = Command::new("logs")
+            .about("print app container logs")
+            .arg(config::runtime_arg())
+            .arg(
+                Arg::new("follow")
+                    .short('f')
+                    .long("follow")
+                    .action(ArgAction::SetTrue)
+                    .help("follow log output"),
+            );
+        let mut stop = Command::new("stop")
+            .about("stop app container")
+            .arg(config::runtime_arg());
+        let mut attach = Command::new("attach")
+            .about("attach to a detached app container")
+            .arg(config::runtime_arg());
+
+        let push = Command::new("push")
+            .about("push the app image to the registry")
+            .arg(config::runtime_arg())
+            .arg(config::registry_arg());
+        let pull = Command::new("pull")
+            .about("pull the app image from the registry instead of building it")
+            .arg(config::runtime_arg())
+            .arg(config::registry_arg());
+
+        let prune = Command::new("prune")
+            .about("remove local images of the app superseded by its current version")
+            .arg(config::runtime_arg())
+            .arg(
+                Arg::new("keep")
+                    .long("keep")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .help("keep the N most recent superseded images (default: 0)"),
+            )
+            .arg(
+                Arg::new("dry-run")
+                    .long("dry-run")
+                    .action(ArgAction::SetTrue)
+                    .help("list the images that would be removed without removing them"),
+            );
+        let export = Command::new("export")
+            .about("save the app image to a tarball for machines without network access")
+            .arg(config::runtime_arg())
+            .arg(
+                Arg::new("output")
+                    .short('o')
+                    .long("output")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("path of the tarball to write (default: <app>-image.tar)"),
+            );
+        let import = Command::new("import")
+            .about("load an app image saved with `export`")
+            .arg(config::runtime_arg())
+            .arg(
+                Arg::new("sha256")
+                    .long("sha256")
+                    .help("expected checksum of the tarball (default: read from <tarball>.sha256)"),
+            )
+            .arg(
+                Arg::new("input")
+                    .required(true)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("tarball written by `export`"),
+            );
 
-        let mut app = Command::new(&self.name).version("0.0").arg(
+        let inspect = Command::new("inspect")
+            .about("show the dfiles metadata recorded in the local app image")
+            .arg(config::runtime_arg());
+
+        let version = self
+            .app_version
+            .clone()
+            .unwrap_or_else(|| String::from("0.0"));
+        let mut app = Command::new(&self.name).version(version).arg(
             Arg::new("verbose")
                 .short('v')
                 .long("verbose")
@@ -246,13 +1309,55 @@ impl ContainerManager {
                 container_paths: self.container_paths.clone(),
             }),
         );
+        // a locked app builds from pinned inputs, which the context hash has to reflect
+        self.lock = Lockfile::load(&self.name)?;
 
         for arg in &config::cli_args() {
             run = run.arg(arg);
             cmd = cmd.arg(arg);
             config = config.arg(arg);
+            explain = explain.arg(arg);
+        }
+        for arg in &config::build_args() {
+            // build's own short flags, e.g. -t for --tag, take precedence
+            let short = arg.get_short();
+            let build_arg = if build.get_arguments().any(|a| a.get_short() == short) {
+                arg.clone().short(None)
+            } else {
+                arg.clone()
+            };
+            build = build.arg(build_arg);
+            generate_archive = generate_archive.arg(arg);
+            dockerfile = dockerfile.arg(arg);
         }
 
+        let dry_run = Arg::new("dry-run")
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("print the container runtime invocation and entrypoint script without running");
+        run = run.arg(&dry_run);
+        cmd = cmd.arg(&dry_run);
+
+        let pull_missing = Arg::new("pull")
+            .long("pull")
+            .action(ArgAction::SetTrue)
+            .help("pull the app image from the registry if it isn't available locally");
+        run = run.arg(&pull_missing);
+        cmd = cmd.arg(&pull_missing);
+
+        run = run.arg(
+            Arg::new("detach")
+                .short('d')
+                .long("detach")
+                .action(ArgAction::SetTrue)
+                .help("run the container in the background; see `ps`, `logs`, `stop` and `attach`"),
+        );
+        run = run.arg(
+            Arg::new("args")
+                .action(ArgAction::Append)
+                .help("extra arguments for the app, e.g. a URL to open"),
+        );
+
         cmd = cmd.arg(
             Arg::new("command")
                 .action(ArgAction::Set)
@@ -275,14 +1380,35 @@ impl ContainerManager {
             for arg in aspect.config_args() {
                 config = config.arg(arg);
             }
+            for arg in aspect.config_args() {
+                explain = explain.arg(arg);
+            }
+            for arg in aspect.config_args() {
+                logs = logs.arg(arg.clone());
+                stop = stop.arg(arg.clone());
+                attach = attach.arg(arg);
+            }
         }
 
         app = app
             .subcommand(run)
             .subcommand(cmd)
             .subcommand(build)
+            .subcommand(lock)
+            .subcommand(push)
+            .subcommand(pull)
+            .subcommand(prune)
+            .subcommand(export)
+            .subcommand(import)
             .subcommand(config)
-            .subcommand(generate_archive);
+            .subcommand(generate_archive)
+            .subcommand(dockerfile)
+            .subcommand(explain)
+            .subcommand(ps)
+            .subcommand(logs)
+            .subcommand(stop)
+            .subcommand(attach)
+            .subcommand(inspect);
 
         let matches = app.get_matches_mut();
 
@@ -291,27 +1417,198 @@ impl ContainerManager {
 
         match matches.subcommand() {
             Some((cmd, subm)) => match cmd {
-                "build" => self.build(),
+                "build" => {
+                    self.enforce_lock = true;
+                    self.load_runtime(subm)?;
+                    self.load_build_config(subm)?;
+                    self.build(subm)?
+                }
+                "lock" => {
+                    self.load_runtime(subm)?;
+                    self.lock()?
+                }
+                "push" => {
+                    self.load_runtime(subm)?;
+                    self.push()?
+                }
+                "pull" => {
+                    self.load_runtime(subm)?;
+                    self.pull()?
+                }
+                "prune" => {
+                    self.load_runtime(subm)?;
+                    self.prune(subm)?
+                }
+                "export" => {
+                    self.load_runtime(subm)?;
+                    self.export(subm)?
+                }
+                "import" => {
+                    self.load_runtime(subm)?;
+                    self.load_build_config(subm)?;
+                    self.import(subm)?
+                }
                 "run" => {
                     self.load_config(subm)?;
-                    self.run(subm)
+                    return self.run(subm);
                 }
                 "cmd" => {
                     self.load_config(subm)?;
-                    self.cmd(subm)
+                    return self.cmd(subm);
                 }
                 "config" => {
                     self.load_config(subm)?;
-                    self.config(subm)
+                    self.config(subm)?
+                }
+                "generate-archive" => {
+                    self.enforce_lock = true;
+                    self.load_build_config(subm)?;
+                    self.generate_archive(subm)?
+                }
+                "dockerfile" => {
+                    self.enforce_lock = true;
+                    self.load_build_config(subm)?;
+                    self.dockerfile()?
+                }
+                "explain" => {
+                    self.load_config(subm)?;
+                    self.explain(subm)?
+                }
+                "inspect" => {
+                    self.load_runtime(subm)?;
+                    self.inspect()?
+                }
+                "ps" => {
+                    self.load_runtime(subm)?;
+                    self.ps()?
+                }
+                "logs" => {
+                    self.load_runtime(subm)?;
+                    return self.logs(subm);
+                }
+                "stop" => {
+                    self.load_runtime(subm)?;
+                    self.stop(subm)?
                 }
-                "generate-archive" => self.generate_archive(),
-                &_ => Ok(println!("{}", app.render_usage())),
+                "attach" => {
+                    self.load_runtime(subm)?;
+                    return self.attach(subm);
+                }
+                &_ => println!("{}", app.render_usage()),
             },
-            None => Ok(println!("{}", app.render_usage())),
+            None => println!("{}", app.render_usage()),
         }
+        Ok(0)
     }
 }
 
+/// What `prune` does with one of the app's local images.
+#[derive(Debug, PartialEq)]
+enum Pruning {
+    /// Tagged with one of the app's current tags.
+    Current,
+    /// One of the `--keep` most recent superseded images.
+    Kept,
+    /// Used by the named running container.
+    InUse(String),
+    Remove,
+}
+
+/// Decides what `prune` does with each of the app's images, newest first.
+fn plan_prune(
+    mut images: Vec<runtime::ImageSummary>,
+    current: &[String],
+    keep: usize,
+    containers: &[runtime::ContainerSummary],
+) -> Vec<(runtime::ImageSummary, Pruning)> {
+    images.sort_by_key(|i| std::cmp::Reverse(i.created));
+    let mut superseded = 0;
+    images
+        .into_iter()
+        .map(|image| {
+            // containers refer to their image by reference, or by short ID once it is untagged
+            let id = image.id.trim_start_matches("sha256:");
+            let user = containers.iter().find(|c| {
+                let used = c.image.trim_start_matches("sha256:");
+                c.is_running()
+                    && (image.tags.contains(&c.image) || (!used.is_empty() && id.starts_with(used)))
+            });
+            let pruning = if image.tags.iter().any(|t| current.contains(t)) {
+                Pruning::Current
+            } else if let Some(container) = user {
+                Pruning::InUse(container.name.clone())
+            } else {
+                superseded += 1;
+                if superseded <= keep {
+                    Pruning::Kept
+                } else {
+                    Pruning::Remove
+                }
+            };
+            (image, pruning)
+        })
+        .collect()
+}
+
+/// Describes a snippet's ordering constraints for `explain`.
+fn constraints(after: &[String], before: &[String]) -> String {
+    let mut parts = Vec::new();
+    if !after.is_empty() {
+        parts.push(format!("after {}", after.join(", ")));
+    }
+    if !before.is_empty() {
+        parts.push(format!("before {}", before.join(", ")));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("  ({})", parts.join("; "))
+    }
+}
+
+/// Hashes the build context so that unchanged images need not be rebuilt.
+fn context_hash(files: &[aspects::ContainerFile]) -> String {
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.container_path.as_bytes());
+        hasher.update([0]);
+        hasher.update((file.contents.len() as u64).to_le_bytes());
+        hasher.update(&file.contents);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn parse_build_arg(value: &String) -> Result<(String, String)> {
+    match value.split_once('=') {
+        Some((key, val)) if !key.is_empty() => Ok((key.to_string(), val.to_string())),
+        _ => Err(Error::InvalidBuildArg(value.to_string())),
+    }
+}
+
+/// Writes the build context files unpacked into `dir`, refusing any whose path would land
+/// outside of it.
+fn write_context_dir(files: &[aspects::ContainerFile], dir: &Path) -> Result<()> {
+    for file in files {
+        let relative = Path::new(file.container_path.trim_start_matches("./"));
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(Error::InvalidContextPath(file.container_path.clone()));
+        }
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &file.contents)?;
+    }
+    Ok(())
+}
+
 fn add_file_to_archive<W: Write>(b: &mut Builder<W>, name: &str, contents: &[u8]) -> Result<()> {
     let mut header = Header::new_gnu();
     header
@@ -332,51 +1629,53 @@ impl aspects::ContainerAspect for Debian {
     fn name(&self) -> String {
         String::from("Debian")
     }
+    fn package_manager(&self) -> Option<PackageManager> {
+        Some(PackageManager::Apt)
+    }
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
+        vec![aspects::DockerfileSnippet {
+            phase: aspects::Phase::Base,
+            instructions: vec![
+                Instruction::From(format!("debian:{}", self.version)),
+                Instruction::label(&[(
+                    runtime::BASE_NAME_LABEL,
+                    format!("debian:{}", self.version),
+                )]),
+            ],
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
         vec![
-            aspects::DockerfileSnippet {
-                order: 00,
-                content: format!("FROM debian:{}", self.version),
-            },
-            aspects::DockerfileSnippet {
-                order: 3,
-                content: String::from(
-                    r#"# Useful language packs
-RUN apt-get update && apt-get install -y --no-install-recommends \
-  fonts-arphic-bkai00mp \
-  fonts-arphic-bsmi00lp \
-  fonts-arphic-gbsn00lp \
-  fonts-arphic-gbsn00lp \
-  \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb"#,
-                ),
-            },
-            aspects::DockerfileSnippet {
-                order: 2,
-                content: String::from(
-                    r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    apt-utils \
-    apt-transport-https \
-    apt \
-    bzip2 \
-    ca-certificates \
-    curl \
-    debian-goodies \
-    dirmngr \
-    gnupg \
-    keychain \
-    lsb-release \
-    locales \
-    lsof \
-    procps \
-    sudo \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
-                ),
-            },
+            aspects::Packages::native(
+                aspects::Phase::SystemPackages,
+                &[
+                    "apt-utils",
+                    "apt-transport-https",
+                    "apt",
+                    "bzip2",
+                    "ca-certificates",
+                    "curl",
+                    "debian-goodies",
+                    "dirmngr",
+                    "gnupg",
+                    "keychain",
+                    "lsb-release",
+                    "locales",
+                    "lsof",
+                    "procps",
+                    "sudo",
+                ],
+            ),
+            // useful language packs
+            aspects::Packages::native(
+                aspects::Phase::SystemPackages,
+                &[
+                    "fonts-arphic-bkai00mp",
+                    "fonts-arphic-bsmi00lp",
+                    "fonts-arphic-gbsn00lp",
+                ],
+            ),
         ]
     }
 }
@@ -390,50 +1689,540 @@ impl aspects::ContainerAspect for Ubuntu {
     fn name(&self) -> String {
         String::from("Ubuntu")
     }
+    fn package_manager(&self) -> Option<PackageManager> {
+        Some(PackageManager::Apt)
+    }
     fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
+        vec![aspects::DockerfileSnippet {
+            phase: aspects::Phase::Base,
+            instructions: vec![
+                Instruction::From(format!("ubuntu:{}", self.version)),
+                Instruction::label(&[(
+                    runtime::BASE_NAME_LABEL,
+                    format!("ubuntu:{}", self.version),
+                )]),
+            ],
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
         vec![
-            aspects::DockerfileSnippet {
-                order: 00,
-                content: format!("FROM ubuntu:{}", self.version),
-            },
-            aspects::DockerfileSnippet {
-                order: 3,
-                content: String::from(
-                    r#"# Useful language packs
-RUN apt-get update && apt-get install -y --no-install-recommends \
-  fonts-arphic-bkai00mp \
-  fonts-arphic-bsmi00lp \
-  fonts-arphic-gbsn00lp \
-  fonts-arphic-gbsn00lp \
-  \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb"#,
+            aspects::Packages::native(
+                aspects::Phase::SystemPackages,
+                &[
+                    "apt-utils",
+                    "apt-transport-https",
+                    "apt",
+                    "bzip2",
+                    "ca-certificates",
+                    "curl",
+                    "debian-goodies",
+                    "dirmngr",
+                    "gnupg",
+                    "keychain",
+                    "lsb-release",
+                    "locales",
+                    "lsof",
+                    "procps",
+                ],
+            ),
+            // useful language packs
+            aspects::Packages::native(
+                aspects::Phase::SystemPackages,
+                &[
+                    "fonts-arphic-bkai00mp",
+                    "fonts-arphic-bsmi00lp",
+                    "fonts-arphic-gbsn00lp",
+                ],
+            ),
+        ]
+    }
+}
+
+#[derive(Clone)]
+struct Fedora {
+    pub version: String,
+}
+
+impl aspects::ContainerAspect for Fedora {
+    fn name(&self) -> String {
+        String::from("Fedora")
+    }
+    fn package_manager(&self) -> Option<PackageManager> {
+        Some(PackageManager::Dnf)
+    }
+    fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
+        vec![aspects::DockerfileSnippet {
+            phase: aspects::Phase::Base,
+            instructions: vec![
+                Instruction::From(format!("fedora:{}", self.version)),
+                Instruction::label(&[(
+                    runtime::BASE_NAME_LABEL,
+                    format!("fedora:{}", self.version),
+                )]),
+            ],
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::native(
+            aspects::Phase::SystemPackages,
+            &[
+                "bzip2",
+                "ca-certificates",
+                "curl",
+                "findutils",
+                "glibc-langpack-en",
+                "gnupg2",
+                "lsof",
+                "procps-ng",
+                "shadow-utils",
+                "sudo",
+            ],
+        )]
+    }
+}
+
+#[derive(Clone)]
+struct Alpine {
+    pub version: String,
+}
+
+impl aspects::ContainerAspect for Alpine {
+    fn name(&self) -> String {
+        String::from("Alpine")
+    }
+    fn package_manager(&self) -> Option<PackageManager> {
+        Some(PackageManager::Apk)
+    }
+    fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
+        vec![aspects::DockerfileSnippet {
+            phase: aspects::Phase::Base,
+            instructions: vec![
+                Instruction::From(format!("alpine:{}", self.version)),
+                Instruction::label(&[(
+                    runtime::BASE_NAME_LABEL,
+                    format!("alpine:{}", self.version),
+                )]),
+            ],
+            ..Default::default()
+        }]
+    }
+    fn packages(&self) -> Vec<aspects::Packages> {
+        vec![aspects::Packages::native(
+            aspects::Phase::SystemPackages,
+            &[
+                "bash",
+                "bzip2",
+                "ca-certificates",
+                "curl",
+                "gnupg",
+                "lsof",
+                "procps",
+                "shadow",
+                "sudo",
+                "tzdata",
+            ],
+        )]
+    }
+}
+
+#[cfg(test)]
+mod containermanager_should {
+    use super::aspects::ContainerAspect;
+    use super::*;
+
+    fn file(path: &str, contents: &str) -> aspects::ContainerFile {
+        aspects::ContainerFile {
+            container_path: path.to_string(),
+            contents: contents.as_bytes().to_vec(),
+        }
+    }
+
+    #[derive(Clone)]
+    struct Snippet(&'static str, Phase, &'static str, &'static [&'static str]);
+
+    impl aspects::ContainerAspect for Snippet {
+        fn name(&self) -> String {
+            self.0.to_string()
+        }
+        fn dockerfile_snippets(&self) -> Vec<aspects::DockerfileSnippet> {
+            vec![aspects::DockerfileSnippet {
+                phase: self.1,
+                content: self.2.to_string(),
+                after: self.3.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            }]
+        }
+    }
+
+    #[test]
+    fn annotate_dockerfile_snippets_with_their_aspect() -> Result<()> {
+        let mgr = ContainerManager::default(
+            String::from("app"),
+            vec![String::from("app:1")],
+            Vec::new(),
+            vec![
+                Box::new(Snippet("Late", Phase::AppInstall, "RUN late", &["Also"])),
+                Box::new(Snippet("Base", Phase::Base, "FROM debian", &[])),
+                Box::new(Snippet("Also", Phase::AppInstall, "RUN also", &[])),
+            ],
+            Vec::new(),
+        )?;
+        let files = mgr.context_files()?;
+        let dockerfile = String::from_utf8_lossy(&files.last().unwrap().contents).to_string();
+        assert!(dockerfile.starts_with(
+            "# Base (base)\nFROM debian\n\n# Also (app-install)\nRUN also\n\n# Late (app-install)\nRUN late\n\n# dfiles (finalize)\nLABEL"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn describe_distro_images_structurally() {
+        let snippets = Debian {
+            version: String::from("bullseye"),
+        }
+        .dockerfile_snippets();
+        assert_eq!(
+            snippets[0].instructions[0],
+            Instruction::From(String::from("debian:bullseye"))
+        );
+        assert!(matches!(snippets[0].instructions[1], Instruction::Label(_)));
+    }
+
+    #[test]
+    fn install_packages_with_the_base_image_package_manager() -> Result<()> {
+        let mgr = ContainerManager::default_fedora(
+            String::from("app"),
+            vec![String::from("app:1")],
+            Vec::new(),
+            vec![Box::new(aspects::Packages::new(
+                Phase::AppInstall,
+                &["libgl1", "libgl1-mesa-glx", "fonts-symbola"],
+            ))],
+            Vec::new(),
+            None,
+        )?;
+        let arranged = mgr.dockerfile_snippets()?;
+        let install = arranged
+            .iter()
+            .find(|p| p.aspect == "Packages" && p.phase == Phase::AppInstall)
+            .map(|p| p.item.render());
+        assert_eq!(
+            install.as_deref(),
+            Some(
+                "RUN dnf install -y --setopt=install_weak_deps=False \\\n    mesa-libGL \\\n    gdouros-symbola-fonts \\\n  && dnf clean all"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn set_up_locale_and_timezone_for_the_base_image() -> Result<()> {
+        type Constructor = fn(
+            String,
+            Vec<String>,
+            Vec<String>,
+            Vec<Box<dyn aspects::ContainerAspect>>,
+            Vec<String>,
+            Option<String>,
+        ) -> Result<ContainerManager>;
+        let distros: [(Constructor, &str, &str); 3] = [
+            (ContainerManager::default_debian, "locale-gen", "apt-get"),
+            (
+                ContainerManager::default_fedora,
+                "glibc-langpack-en",
+                "dnf install",
+            ),
+            (ContainerManager::default_alpine, "ENV LANG", "apk add"),
+        ];
+        for (constructor, locale_setup, install) in distros {
+            let mgr = constructor(
+                String::from("app"),
+                vec![String::from("app:1")],
+                Vec::new(),
+                vec![
+                    Box::new(aspects::Locale::try_from(&String::from("en_US.UTF-8"))?),
+                    Box::new(aspects::Timezone(String::from("America/Chicago"))),
+                ],
+                Vec::new(),
+                None,
+            )?;
+            let dockerfile: String = mgr
+                .dockerfile_snippets()?
+                .iter()
+                .map(|p| p.item.render())
+                .collect();
+            assert!(dockerfile.contains(locale_setup), "{}", dockerfile);
+            assert!(dockerfile.contains(install), "{}", dockerfile);
+            assert!(dockerfile.contains("tzdata"), "{}", dockerfile);
+            assert!(
+                dockerfile.contains("ENV LANG=en_US.UTF-8"),
+                "{}",
+                dockerfile
+            );
+
+            let script: String = entrypoint::arrange(&mgr.aspects, mgr.package_manager())?
+                .iter()
+                .map(|s| s.snippet.clone())
+                .collect();
+            assert_eq!(
+                dockerfile.contains("locale-gen"),
+                script.contains("locale-gen")
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn build_from_locked_inputs() -> Result<()> {
+        let mut mgr = ContainerManager::default_debian(
+            String::from("app"),
+            vec![String::from("app:1")],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Some(String::from("bookworm")),
+        )?;
+        assert_eq!(
+            mgr.inputs(),
+            (vec![String::from("debian:bookworm")], Vec::new())
+        );
+
+        mgr.lock = Some(Lockfile {
+            images: BTreeMap::from([(
+                String::from("debian:bookworm"),
+                String::from("sha256:0123"),
+            )]),
+            ..Default::default()
+        });
+        let dockerfile = String::from_utf8(mgr.context_files()?.pop().unwrap().contents).unwrap();
+        assert!(dockerfile.contains("\nFROM debian@sha256:0123\n"));
+
+        // inputs missing from the lockfile only stop subcommands that build the image
+        mgr.lock = Some(Lockfile::default());
+        let dockerfile = String::from_utf8(mgr.context_files()?.pop().unwrap().contents).unwrap();
+        assert!(dockerfile.contains("\nFROM debian:bookworm\n"));
+        mgr.enforce_lock = true;
+        assert!(matches!(mgr.context_files(), Err(Error::UnlockedInput(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn write_the_build_context_as_an_archive_or_directory() -> Result<()> {
+        let mgr = ContainerManager::default_debian(
+            String::from("app"),
+            vec![String::from("app:1")],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Some(String::from("bookworm")),
+        )?;
+        let out = tempfile::tempdir()?;
+        let path_arg = |name: &'static str| {
+            Arg::new(name)
+                .long(name)
+                .value_parser(clap::value_parser!(PathBuf))
+        };
+        let cli = Command::new("generate-archive")
+            .arg(path_arg("output"))
+            .arg(path_arg("dir"));
+
+        let archive = out.path().join("context.tar");
+        mgr.generate_archive(&cli.clone().get_matches_from([
+            "generate-archive",
+            "--output",
+            archive.to_str().unwrap(),
+        ]))?;
+        let names: Vec<PathBuf> = tar::Archive::new(File::open(&archive)?)
+            .entries()?
+            .map(|e| Ok(e?.path()?.to_path_buf()))
+            .collect::<Result<_>>()?;
+        assert_eq!(names, vec![PathBuf::from("Dockerfile")]);
+
+        let dir = out.path().join("context");
+        mgr.generate_archive(&cli.get_matches_from([
+            "generate-archive",
+            "--dir",
+            dir.to_str().unwrap(),
+        ]))?;
+        assert!(fs::read_to_string(dir.join("Dockerfile"))?.contains("FROM debian:bookworm"));
+
+        for escaping in ["../outside", "/etc/outside", "nested/../../outside"] {
+            assert!(matches!(
+                write_context_dir(&[file(escaping, "x")], &dir),
+                Err(Error::InvalidContextPath(_))
+            ));
+        }
+        write_context_dir(&[file("./nested/inside", "x")], &dir)?;
+        assert!(dir.join("nested/inside").exists());
+        assert!(!out.path().join("outside").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn keep_only_detached_entrypoints() -> Result<()> {
+        let mgr = ContainerManager::default_debian(
+            String::from("app"),
+            vec![String::from("app:1")],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Some(String::from("bookworm")),
+        )?;
+        let flag = |name: &'static str| Arg::new(name).long(name).action(ArgAction::SetTrue);
+        let cli = Command::new("run").arg(flag("detach")).arg(flag("dry-run"));
+
+        for argv in [
+            vec!["run"],
+            vec!["run", "--dry-run"],
+            vec!["run", "--detach", "--dry-run"],
+        ] {
+            let matches = cli.clone().get_matches_from(argv);
+            assert_eq!(mgr.entrypoint_dir(&matches)?, mgr.tempdir.path());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_build_args() -> Result<()> {
+        assert_eq!(
+            parse_build_arg(&String::from("CHANNEL=beta=1"))?,
+            (String::from("CHANNEL"), String::from("beta=1"))
+        );
+        assert_eq!(
+            parse_build_arg(&String::from("EMPTY="))?,
+            (String::from("EMPTY"), String::new())
+        );
+        assert!(parse_build_arg(&String::from("=beta")).is_err());
+        assert!(parse_build_arg(&String::from("CHANNEL")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn bake_build_time_config_into_images() -> Result<()> {
+        let mut mgr = ContainerManager::default_debian(
+            String::from("app"),
+            vec![String::from("app:1")],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Some(String::from("bookworm")),
+        )?;
+        let unconfigured = context_hash(&mgr.context_files()?);
+
+        let mut cfg = config::Config::empty();
+        cfg.timezone = Some(aspects::Timezone(String::from("America/Chicago")));
+        cfg.memory = Some(aspects::Memory::try_from(&String::from("1g"))?);
+        mgr.add_build_config(&cfg)?;
+
+        let names: Vec<String> = mgr.aspects.iter().map(|a| a.name()).collect();
+        assert!(names.contains(&String::from("Timezone")));
+        assert!(!names.contains(&String::from("Memory")));
+        assert_ne!(mgr.context_hash, unconfigured);
+
+        let dockerfile = String::from_utf8(mgr.context_files()?.pop().unwrap().contents).unwrap();
+        assert!(dockerfile.contains("/usr/share/zoneinfo/America/Chicago"));
+        assert!(dockerfile.contains("dfiles.config=\"timezone=America/Chicago\""));
+        Ok(())
+    }
+
+    #[test]
+    fn prune_superseded_images_not_in_use() {
+        let image = |id: &str, tag: &str, created: i64| runtime::ImageSummary {
+            id: id.to_string(),
+            tags: vec![tag.to_string()],
+            created,
+        };
+        let running = runtime::ContainerSummary {
+            id: String::from("c1"),
+            name: String::from("firefox-default"),
+            image: String::from("waynr/firefox:127"),
+            state: String::from("running"),
+            status: String::new(),
+            labels: Default::default(),
+        };
+        let plan = plan_prune(
+            vec![
+                image("sha256:a", "waynr/firefox:126", 1),
+                image("sha256:b", "waynr/firefox:129", 4),
+                image("sha256:c", "waynr/firefox:128", 3),
+                image("sha256:d", "waynr/firefox:127", 2),
+                image("sha256:e", "waynr/firefox:125", 0),
+            ],
+            &[String::from("waynr/firefox:129")],
+            1,
+            &[running],
+        );
+        let plan: Vec<(String, Pruning)> = plan.into_iter().map(|(i, p)| (i.id, p)).collect();
+        assert_eq!(
+            plan,
+            vec![
+                (String::from("sha256:b"), Pruning::Current),
+                (String::from("sha256:c"), Pruning::Kept),
+                (
+                    String::from("sha256:d"),
+                    Pruning::InUse(String::from("firefox-default"))
                 ),
-            },
-            aspects::DockerfileSnippet {
-                order: 2,
-                content: String::from(
-                    r#"RUN apt-get update && apt-get install -y \
-    --no-install-recommends \
-    apt-utils \
-    apt-transport-https \
-    apt \
-    bzip2 \
-    ca-certificates \
-    curl \
-    debian-goodies \
-    dirmngr \
-    gnupg \
-    keychain \
-    lsb-release \
-    locales \
-    lsof \
-    procps \
-  && apt-get purge --autoremove \
-  && rm -rf /var/lib/apt/lists/* \
-  && rm -rf /src/*.deb "#,
+                (String::from("sha256:a"), Pruning::Remove),
+                (String::from("sha256:e"), Pruning::Remove),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_package_installs_per_phase() -> Result<()> {
+        let mgr = ContainerManager::default(
+            String::from("app"),
+            vec![String::from("app:1")],
+            Vec::new(),
+            vec![
+                Box::new(Snippet("Base", Phase::Base, "FROM debian", &[])),
+                Box::new(aspects::Packages::new(
+                    Phase::AppInstall,
+                    &["libgbm1", "curl"],
+                )),
+                Box::new(aspects::Packages::new(
+                    Phase::SystemPackages,
+                    &["curl", "sudo"],
+                )),
+                Box::new(aspects::Packages::new(
+                    Phase::SystemPackages,
+                    &["sudo", "lsof"],
+                )),
+            ],
+            Vec::new(),
+        )?;
+        let arranged = mgr.dockerfile_snippets()?;
+        let installs: Vec<(Phase, &Instruction)> = arranged
+            .iter()
+            .filter(|p| p.aspect == "Packages")
+            .map(|p| (p.phase, &p.item.instructions[0]))
+            .collect();
+        assert_eq!(
+            installs,
+            vec![
+                (
+                    Phase::SystemPackages,
+                    &Instruction::apt_install(&["curl", "sudo", "lsof"])
                 ),
-            },
-        ]
+                (Phase::AppInstall, &Instruction::apt_install(&["libgbm1"])),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hash_build_contexts_by_content() {
+        let context = vec![file("a", "bc"), file("Dockerfile", "FROM debian")];
+        assert_eq!(context_hash(&context), context_hash(&context));
+        assert_ne!(
+            context_hash(&context),
+            context_hash(&[file("ab", "c"), file("Dockerfile", "FROM debian")])
+        );
+        assert_ne!(
+            context_hash(&context),
+            context_hash(&[file("a", "bc"), file("Dockerfile", "FROM ubuntu")])
+        );
     }
 }
@@ -1,14 +1,189 @@
-use std::process::Command;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
 
-pub fn run(args: Vec<String>) {
-    let cmdstr: String = args.join(" ");
-    log::debug!("docker run {}", cmdstr);
+use dockworker::ContainerBuildOptions;
+use serde::Deserialize;
+use serde_json::from_str;
 
-    let mut child = Command::new("docker")
-        .arg("run")
-        .args(args)
-        .spawn()
-        .expect("meow");
+use super::error::{Error, Result};
+use super::runtime::{BuildOptions, ContainerRuntime};
 
-    let _ = child.wait().expect("failed waiting for child process");
+/// A build progress message as streamed by the engine's `/build` endpoint.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct BuildMessage {
+    stream: Option<String>,
+    status: Option<String>,
+    progress: Option<String>,
+    id: Option<String>,
+    error: Option<String>,
+    error_detail: Option<ErrorDetail>,
+    aux: Option<BuildAux>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct BuildAux {
+    #[serde(rename = "ID")]
+    id: Option<String>,
+}
+
+/// Docker runtime; images are built through the engine API and containers are run with the
+/// `docker` CLI.
+pub struct Docker {
+    client: dockworker::Docker,
+}
+
+impl Docker {
+    pub fn connect() -> Result<Docker> {
+        Ok(Docker {
+            client: dockworker::Docker::connect_with_defaults()?,
+        })
+    }
+}
+
+impl ContainerRuntime for Docker {
+    fn program(&self) -> &str {
+        "docker"
+    }
+
+    fn build(&self, options: &BuildOptions, context: &Path) -> Result<String> {
+        build_image(&self.client, options, context)
+    }
+}
+
+/// Builds an image through the engine API, printing the build output as it streams in, and returns
+/// the ID of the built image.
+pub(crate) fn build_image(
+    client: &dockworker::Docker,
+    options: &BuildOptions,
+    context: &Path,
+) -> Result<String> {
+    let quiet = options.quiet;
+    let options = ContainerBuildOptions {
+        dockerfile: options.dockerfile.clone(),
+        t: options.tags.clone(),
+        q: options.quiet,
+        nocache: options.no_cache,
+        pull: options.pull.then(|| String::from("true")),
+        buildargs: (!options.build_args.is_empty())
+            .then(|| options.build_args.iter().cloned().collect()),
+        labels: (!options.labels.is_empty()).then(|| options.labels.iter().cloned().collect()),
+        ..ContainerBuildOptions::default()
+    };
+
+    let res = client.build_image(options, context)?;
+    if quiet {
+        process_build_output(BufReader::new(res), &mut io::sink())
+    } else {
+        process_build_output(BufReader::new(res), &mut io::stdout())
+    }
+}
+
+/// Echoes build progress messages to `out` and returns the built image ID, or the failing
+/// Dockerfile step along with the daemon's message if the build failed.
+fn process_build_output<R: BufRead, W: Write>(reader: R, out: &mut W) -> Result<String> {
+    let mut step = String::new();
+    let mut image_id = None;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = match from_str::<BuildMessage>(&line) {
+            Ok(m) => m,
+            Err(_) => {
+                log::debug!("unrecognized build output: {}", line);
+                continue;
+            }
+        };
+
+        if let Some(text) = message.stream {
+            if text.starts_with("Step ") {
+                step = text.trim_end().to_string();
+            }
+            if let Some(id) = text.trim().strip_prefix("Successfully built ") {
+                image_id.get_or_insert_with(|| id.to_string());
+            }
+            // quiet builds report nothing but the image ID
+            if text.starts_with("sha256:") {
+                image_id.get_or_insert_with(|| text.trim().to_string());
+            }
+            write!(out, "{}", text)?;
+        }
+        if let Some(status) = message.status {
+            match (message.id, message.progress) {
+                (Some(id), Some(progress)) => writeln!(out, "{}: {} {}", id, status, progress)?,
+                (Some(id), None) => writeln!(out, "{}: {}", id, status)?,
+                (None, _) => writeln!(out, "{}", status)?,
+            }
+        }
+        if let Some(id) = message.aux.and_then(|a| a.id) {
+            image_id = Some(id);
+        }
+        if let Some(error) = message.error_detail.map(|d| d.message).or(message.error) {
+            return Err(Error::BuildFailed {
+                step,
+                message: error,
+            });
+        }
+    }
+
+    image_id.ok_or(Error::BuildFailed {
+        step,
+        message: String::from("no image ID was reported"),
+    })
+}
+
+#[cfg(test)]
+mod docker_should {
+    use super::*;
+
+    #[test]
+    fn report_the_failing_build_step() {
+        let output = br#"{"stream":"Step 1/2 : FROM debian:bullseye"}
+{"stream":"\n"}
+{"status":"Downloading","progressDetail":{"current":1,"total":2},"progress":"[=>  ]","id":"abc"}
+{"stream":"Step 2/2 : RUN apt-get install -y nope"}
+{"stream":"\n"}
+{"errorDetail":{"code":100,"message":"The command '/bin/sh -c apt-get install -y nope' returned a non-zero code: 100"},"error":"The command '/bin/sh -c apt-get install -y nope' returned a non-zero code: 100"}
+"#;
+        let mut out = Vec::new();
+        match process_build_output(&output[..], &mut out) {
+            Err(Error::BuildFailed { step, message }) => {
+                assert_eq!(step, "Step 2/2 : RUN apt-get install -y nope");
+                assert!(message.ends_with("returned a non-zero code: 100"));
+            }
+            other => panic!("expected a build failure, got {:?}", other),
+        }
+        assert!(String::from_utf8_lossy(&out).contains("abc: Downloading [=>  ]"));
+    }
+
+    #[test]
+    fn return_the_built_image_id() -> Result<()> {
+        let output = br#"{"stream":"Step 1/1 : FROM debian:bullseye"}
+{"stream":"\n"}
+{"aux":{"ID":"sha256:0123"}}
+{"stream":"Successfully built 0123\n"}
+"#;
+        let mut out = Vec::new();
+        assert_eq!(process_build_output(&output[..], &mut out)?, "sha256:0123");
+        Ok(())
+    }
+
+    #[test]
+    fn return_the_image_id_of_quiet_builds() -> Result<()> {
+        let output = br#"{"stream":"sha256:4567\n"}
+"#;
+        assert_eq!(
+            process_build_output(&output[..], &mut io::sink())?,
+            "sha256:4567"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,179 @@
+use std::fmt;
+
+use super::packages::PackageManager;
+
+/// A single Dockerfile instruction. Aspects describe their build steps with these so that the
+/// generated Dockerfile can be inspected and tested without parsing text; `Raw` is available for
+/// anything the model doesn't cover.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    From(String),
+    /// Shell commands chained with `&&` into a single layer.
+    Run(Vec<String>),
+    /// Distro packages installed in a single `RUN` layer by the base image's package manager.
+    Install {
+        manager: PackageManager,
+        packages: Vec<String>,
+    },
+    Env(Vec<(String, String)>),
+    Copy {
+        src: String,
+        dest: String,
+    },
+    Add {
+        src: String,
+        dest: String,
+    },
+    Workdir(String),
+    Label(Vec<(String, String)>),
+    User(String),
+    /// Exec form entrypoint.
+    Entrypoint(Vec<String>),
+    Raw(String),
+}
+
+impl Instruction {
+    /// A `RUN` instruction with a single command.
+    pub fn run(command: &str) -> Instruction {
+        Instruction::Run(vec![command.to_string()])
+    }
+
+    /// A single `ENV` variable.
+    pub fn env(key: &str, value: &str) -> Instruction {
+        Instruction::Env(vec![(key.to_string(), value.to_string())])
+    }
+
+    /// A `LABEL` instruction with the given key/value pairs.
+    pub fn label(labels: &[(&str, String)]) -> Instruction {
+        Instruction::Label(
+            labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        )
+    }
+
+    /// Installs Debian packages without recommends and cleans up the apt caches in the same
+    /// layer.
+    pub fn apt_install(packages: &[&str]) -> Instruction {
+        PackageManager::Apt.install(packages)
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Renders key/value pairs, quoting values only where needed unless `always` is set.
+fn pairs(values: &[(String, String)], always: bool) -> String {
+    values
+        .iter()
+        .map(|(k, v)| {
+            let plain = !v.is_empty()
+                && !v
+                    .chars()
+                    .any(|c| c.is_whitespace() || c == '"' || c == '\\' || c == '$');
+            if always || !plain {
+                format!("{}={}", k, quote(v))
+            } else {
+                format!("{}={}", k, v)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" \\\n      ")
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::From(image) => write!(f, "FROM {}", image),
+            Instruction::Run(commands) => write!(f, "RUN {}", commands.join(" \\\n  && ")),
+            Instruction::Install { manager, packages } => write!(
+                f,
+                "RUN {}",
+                manager.install_commands(packages).join(" \\\n  && ")
+            ),
+            Instruction::Env(values) => write!(f, "ENV {}", pairs(values, false)),
+            Instruction::Copy { src, dest } => write!(f, "COPY {} {}", src, dest),
+            Instruction::Add { src, dest } => write!(f, "ADD {} {}", src, dest),
+            Instruction::Workdir(dir) => write!(f, "WORKDIR {}", dir),
+            Instruction::Label(labels) => write!(f, "LABEL {}", pairs(labels, true)),
+            Instruction::User(user) => write!(f, "USER {}", user),
+            Instruction::Entrypoint(args) => {
+                let args: Vec<String> = args.iter().map(|a| quote(a)).collect();
+                write!(f, "ENTRYPOINT [{}]", args.join(", "))
+            }
+            Instruction::Raw(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Renders instructions one per line.
+pub fn render(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(Instruction::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod dockerfile_should {
+    use super::*;
+
+    #[test]
+    fn render_instructions() {
+        assert_eq!(
+            render(&[
+                Instruction::From(String::from("debian:bullseye")),
+                Instruction::env("TZ", "America/Chicago"),
+                Instruction::env("GREETING", "hello there"),
+                Instruction::Copy {
+                    src: String::from("/etc/fonts/local.conf"),
+                    dest: String::from("/etc/fonts/local.conf"),
+                },
+                Instruction::Workdir(String::from("/opt/")),
+                Instruction::User(String::from("root")),
+                Instruction::Entrypoint(vec![String::from("/entrypoint.bash")]),
+                Instruction::Run(vec![String::from("true"), String::from("false")]),
+            ]),
+            r#"FROM debian:bullseye
+ENV TZ=America/Chicago
+ENV GREETING="hello there"
+COPY /etc/fonts/local.conf /etc/fonts/local.conf
+WORKDIR /opt/
+USER root
+ENTRYPOINT ["/entrypoint.bash"]
+RUN true \
+  && false"#
+        );
+    }
+
+    #[test]
+    fn render_labels() {
+        assert_eq!(
+            Instruction::label(&[
+                ("org.opencontainers.image.title", String::from("firefox")),
+                ("dfiles.aspects", String::from(r#"Say "hi" \ bye"#)),
+            ])
+            .to_string(),
+            r#"LABEL org.opencontainers.image.title="firefox" \
+      dfiles.aspects="Say \"hi\" \\ bye""#
+        );
+    }
+
+    #[test]
+    fn render_apt_installs() {
+        let install = Instruction::apt_install(&["libpulse0", "libxtst6"]);
+        assert_eq!(
+            install,
+            Instruction::Install {
+                manager: PackageManager::Apt,
+                packages: vec![String::from("libpulse0"), String::from("libxtst6")],
+            }
+        );
+        assert!(install.to_string().starts_with(
+            "RUN apt-get update \\\n  && apt-get install -y --no-install-recommends \\\n    libpulse0"
+        ));
+    }
+}
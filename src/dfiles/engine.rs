@@ -0,0 +1,992 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use dockworker::{ContainerCreateOptions, ContainerHostConfig, CreateExecOptions, DeviceMapping};
+use serde::Deserialize;
+
+use super::docker;
+use super::error::{Error, Result};
+use super::registry;
+use super::runtime::{
+    self, split_tag, BuildOptions, ContainerRuntime, ContainerSummary, ImageInfo, ImageSummary,
+};
+use super::signals;
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// Typed equivalent of the `docker run` command line arguments generated by aspects.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RunConfig {
+    pub name: Option<String>,
+    pub image: String,
+    pub cmd: Vec<String>,
+    pub entrypoint: Option<String>,
+    pub env: Vec<String>,
+    pub binds: Vec<String>,
+    pub devices: Vec<String>,
+    pub cap_add: Vec<String>,
+    pub network_mode: Option<String>,
+    pub memory: Option<u64>,
+    pub cpu_shares: Option<u64>,
+    pub labels: Vec<String>,
+    pub init: bool,
+    pub remove: bool,
+    pub detach: bool,
+    pub tty: bool,
+    pub interactive: bool,
+}
+
+impl RunConfig {
+    pub fn create_options(&self) -> ContainerCreateOptions {
+        let mut host_config = ContainerHostConfig::new();
+        for bind in &self.binds {
+            host_config.binds(bind.clone());
+        }
+        for device in &self.devices {
+            host_config.devices(device_mapping(device));
+        }
+        for cap in &self.cap_add {
+            host_config.cap_add(cap.clone());
+        }
+        if let Some(mode) = &self.network_mode {
+            host_config.network_mode(mode.clone());
+        }
+        if let Some(memory) = self.memory {
+            host_config.memory(memory);
+        }
+        if let Some(shares) = self.cpu_shares {
+            host_config.cpu_shares(shares);
+        }
+        // attached containers are removed explicitly once they have been waited on
+        host_config.auto_remove(self.remove && self.detach);
+
+        let mut options = ContainerCreateOptions::new(&self.image);
+        options
+            .tty(self.tty)
+            .open_stdin(self.interactive)
+            .stdin_once(self.interactive)
+            .attach_stdin(self.interactive)
+            .host_config(host_config);
+        for var in &self.env {
+            options.env(var.clone());
+        }
+        for arg in &self.cmd {
+            options.cmd(arg.clone());
+        }
+        for label in &self.labels {
+            let (key, value) = label.split_once('=').unwrap_or((label, ""));
+            options.label(key.to_string(), value.to_string());
+        }
+        if let Some(entrypoint) = &self.entrypoint {
+            options.entrypoint(vec![entrypoint.clone()]);
+        }
+        options
+    }
+}
+
+impl TryFrom<&[String]> for RunConfig {
+    type Error = Error;
+    fn try_from(args: &[String]) -> Result<Self> {
+        let mut config = RunConfig::default();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let mut value = |flag: &str| {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| Error::InvalidRunArg(flag.to_string(), String::new()))
+            };
+            match arg.as_str() {
+                "--rm" => config.remove = true,
+                "--init" => config.init = true,
+                "-d" | "--detach" => config.detach = true,
+                "-it" | "-ti" => {
+                    config.tty = true;
+                    config.interactive = true;
+                }
+                "-t" | "--tty" => config.tty = true,
+                "-i" | "--interactive" => config.interactive = true,
+                "--name" => config.name = Some(value(arg)?),
+                "-e" | "--env" => config.env.push(value(arg)?),
+                "-v" | "--volume" => config.binds.push(value(arg)?),
+                "--device" => config.devices.push(value(arg)?),
+                "--cap-add" => config.cap_add.push(value(arg)?),
+                "-l" | "--label" => config.labels.push(value(arg)?),
+                "--net" | "--network" => config.network_mode = Some(value(arg)?),
+                "--entrypoint" => config.entrypoint = Some(value(arg)?),
+                "--memory" => {
+                    let v = value(arg)?;
+                    config.memory =
+                        Some(parse_bytes(&v).ok_or(Error::InvalidRunArg(arg.clone(), v))?);
+                }
+                "--cpu-shares" => {
+                    let v = value(arg)?;
+                    config.cpu_shares = Some(
+                        v.parse()
+                            .map_err(|_| Error::InvalidRunArg(arg.clone(), v))?,
+                    );
+                }
+                s if s.starts_with('-') => return Err(Error::UnsupportedRunArg(s.to_string())),
+                image => {
+                    config.image = image.to_string();
+                    config.cmd = iter.cloned().collect();
+                    break;
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Parses a docker-style memory size such as `512m`, `1g` or `1024mb` into bytes.
+fn parse_bytes(value: &str) -> Option<u64> {
+    let lower = value.to_lowercase();
+    let trimmed = lower.strip_suffix('b').unwrap_or(&lower);
+    let (digits, multiplier) = match trimmed.chars().last()? {
+        'k' => (&trimmed[..trimmed.len() - 1], 1 << 10),
+        'm' => (&trimmed[..trimmed.len() - 1], 1 << 20),
+        'g' => (&trimmed[..trimmed.len() - 1], 1 << 30),
+        _ => (trimmed, 1),
+    };
+    digits.parse::<u64>().ok().map(|n| n * multiplier)
+}
+
+fn device_mapping(device: &str) -> DeviceMapping {
+    let parts: Vec<&str> = device.split(':').collect();
+    let host = parts[0];
+    let container = parts.get(1).unwrap_or(&host);
+    let permissions = parts.get(2).unwrap_or(&"rwm");
+    DeviceMapping::new(
+        PathBuf::from(host),
+        PathBuf::from(container),
+        permissions.to_string(),
+    )
+}
+
+/// Docker runtime that talks to the engine socket directly for both building and running, so no
+/// `docker` CLI is needed on the host.
+pub struct Engine {
+    client: dockworker::Docker,
+    socket: PathBuf,
+}
+
+impl Engine {
+    pub fn connect() -> Result<Engine> {
+        let host = env::var("DOCKER_HOST").unwrap_or_else(|_| format!("unix://{}", DEFAULT_SOCKET));
+        match host.strip_prefix("unix://") {
+            Some(path) => Engine::connect_with_unix(Path::new(path)),
+            None => Err(Error::UnsupportedDockerHost(host)),
+        }
+    }
+
+    pub fn connect_with_unix(socket: &Path) -> Result<Engine> {
+        Ok(Engine {
+            client: dockworker::Docker::connect_with_unix(&socket.to_string_lossy())?,
+            socket: socket.to_path_buf(),
+        })
+    }
+
+    fn open(&self, method: &str, path: &str) -> Result<(u16, UnixStream)> {
+        send(&self.socket, method, path, &[], None)
+    }
+
+    /// Sends a bodyless request and returns the response status and body.
+    fn request(&self, method: &str, path: &str) -> Result<(u16, Vec<u8>)> {
+        read_body(self.open(method, path)?)
+    }
+
+    /// Sends a request on behalf of a registry operation on `image`, authenticated with the
+    /// credentials the Docker config has for its registry. Failures after the engine accepted the
+    /// request are reported in its progress stream rather than by status.
+    fn registry_request(&self, path: &str, image: &str) -> Result<bool> {
+        let auth = registry::auth_header(image)?;
+        let (status, body) = read_body(send(
+            &self.socket,
+            "POST",
+            path,
+            &[("X-Registry-Auth", &auth)],
+            None,
+        )?)?;
+        let body = String::from_utf8_lossy(&body);
+        log::debug!("{}", body);
+        Ok(status == 200 && !body.contains("\"error\""))
+    }
+
+    /// Sends the file at `file` as a tar request body, streaming it rather than reading it into
+    /// memory, and returns the response status and body.
+    fn upload(&self, path: &str, file: &Path) -> Result<(u16, Vec<u8>)> {
+        let mut source = File::open(file)?;
+        let mut stream = UnixStream::connect(&self.socket)?;
+        write!(
+            stream,
+            "POST {} HTTP/1.0\r\nHost: docker\r\nContent-Type: application/x-tar\r\nContent-Length: {}\r\n\r\n",
+            path,
+            source.metadata()?.len()
+        )?;
+        io::copy(&mut source, &mut stream)?;
+        let status = read_response_head(&mut stream)?;
+        read_body((status, stream))
+    }
+
+    /// Sends a JSON request and returns the response status and body.
+    fn post_json(&self, path: &str, value: &serde_json::Value) -> Result<(u16, Vec<u8>)> {
+        let body = serde_json::to_vec(value).map_err(io::Error::from)?;
+        read_body(send(&self.socket, "POST", path, &[], Some(&body))?)
+    }
+
+    /// Creates the container and returns its id. The request body is patched by hand because
+    /// dockworker's host config has no `Init` field.
+    fn create(&self, config: &RunConfig) -> Result<String> {
+        let mut options = serde_json::to_value(config.create_options()).map_err(io::Error::from)?;
+        if config.init {
+            options["HostConfig"]["Init"] = serde_json::Value::Bool(true);
+        }
+        let path = match &config.name {
+            Some(name) => format!("/containers/create?name={}", percent_encode(name)),
+            None => String::from("/containers/create"),
+        };
+
+        let (status, body) = self.post_json(&path, &options)?;
+        let created: CreatedContainer = match status {
+            201 => serde_json::from_slice(&body).map_err(io::Error::from)?,
+            _ => {
+                return Err(Error::EngineRequestFailed(
+                    status,
+                    String::from_utf8_lossy(&body).to_string(),
+                ))
+            }
+        };
+        Ok(created.id)
+    }
+
+    /// Sends the engine's quit signals on to the given container, as `docker run` does with
+    /// `--sig-proxy`.
+    fn forward_signals(&self, id: &str) -> Result<signals::Forwarder> {
+        let socket = self.socket.clone();
+        let path = format!("/containers/{}/kill?signal=", id);
+        signals::Forwarder::start(move |signal| {
+            let _ = send(&socket, "POST", &format!("{}{}", path, signal), &[], None);
+        })
+    }
+
+    /// Returns the TTY and stdin settings the container was created with.
+    fn container_stdio(&self, container: &str) -> Result<ContainerStdio> {
+        let (status, body) = self.request("GET", &format!("/containers/{}/json", container))?;
+        if status != 200 {
+            return Err(Error::EngineRequestFailed(
+                status,
+                String::from_utf8_lossy(&body).to_string(),
+            ));
+        }
+        let inspected: InspectedContainer = serde_json::from_slice(&body)
+            .map_err(|_| Error::EngineRequestFailed(status, format!("inspect {}", container)))?;
+        Ok(inspected.config)
+    }
+
+    /// Attaches to the container's stdio over a hijacked connection and starts copying. The
+    /// returned handle finishes when the container closes its output.
+    fn hijack(&self, id: &str, tty: bool, stdin: bool) -> Result<thread::JoinHandle<()>> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        write!(
+            stream,
+            "POST /containers/{}/attach?stream=1&stdin={}&stdout=1&stderr=1 HTTP/1.1\r\n\
+             Host: docker\r\nConnection: Upgrade\r\nUpgrade: tcp\r\n\r\n",
+            id, stdin as u8,
+        )?;
+        let status = read_response_head(&mut stream)?;
+        if status != 101 && status != 200 {
+            return Err(Error::EngineRequestFailed(status, format!("attach {}", id)));
+        }
+
+        if stdin {
+            let mut writer = stream.try_clone()?;
+            thread::spawn(move || {
+                let _ = io::copy(&mut io::stdin(), &mut writer);
+                let _ = writer.shutdown(std::net::Shutdown::Write);
+            });
+        }
+
+        Ok(thread::spawn(move || {
+            let _ = if tty {
+                io::copy(&mut stream, &mut io::stdout()).map(|_| ())
+            } else {
+                demultiplex(&mut stream)
+            };
+        }))
+    }
+}
+
+impl ContainerRuntime for Engine {
+    fn program(&self) -> &str {
+        "docker"
+    }
+
+    fn build(&self, options: &BuildOptions, context: &Path) -> Result<String> {
+        docker::build_image(&self.client, options, context)
+    }
+
+    fn run(&self, args: Vec<String>) -> Result<i32> {
+        let config = RunConfig::try_from(args.as_slice())?;
+        log::debug!("{:?}", config);
+
+        let id = self.create(&config)?;
+        let id = id.as_str();
+
+        if config.detach {
+            self.client.start_container(id)?;
+            println!("{}", id);
+            return Ok(0);
+        }
+
+        let _signals = self.forward_signals(id)?;
+        let _terminal = if config.tty {
+            RawTerminal::enable()
+        } else {
+            None
+        };
+        let output = self.hijack(id, config.tty, config.interactive);
+        let started = output.and_then(|o| {
+            self.client.start_container(id)?;
+            Ok(o)
+        });
+        let output = match started {
+            Ok(o) => o,
+            Err(e) => {
+                let _ = self.client.remove_container(id, None, Some(true), None);
+                return Err(e);
+            }
+        };
+
+        let _ = output.join();
+        let code = self.client.wait_container(id)?.into_inner();
+
+        if config.remove {
+            self.client.remove_container(id, None, None, None)?;
+        }
+        Ok(code)
+    }
+
+    fn inspect_image(&self, image: &str) -> Result<Option<ImageInfo>> {
+        let (status, body) = self.request("GET", &format!("/images/{}/json", image))?;
+        match status {
+            404 => Ok(None),
+            200 => Ok(Some(
+                serde_json::from_slice(&body).map_err(|_| Error::FailedToInspectImage)?,
+            )),
+            _ => Err(Error::EngineRequestFailed(
+                status,
+                String::from_utf8_lossy(&body).to_string(),
+            )),
+        }
+    }
+
+    fn pull(&self, image: &str) -> Result<()> {
+        let (repo, tag) = split_tag(image);
+        let path = format!(
+            "/images/create?fromImage={}&tag={}",
+            percent_encode(repo),
+            percent_encode(tag)
+        );
+        match self.registry_request(&path, image)? {
+            true => Ok(()),
+            false => Err(Error::FailedToPullImage(image.to_string())),
+        }
+    }
+
+    fn tag(&self, source: &str, target: &str) -> Result<()> {
+        let (repo, tag) = split_tag(target);
+        let (status, _) = self.request(
+            "POST",
+            &format!(
+                "/images/{}/tag?repo={}&tag={}",
+                source,
+                percent_encode(repo),
+                percent_encode(tag)
+            ),
+        )?;
+        match status {
+            201 => Ok(()),
+            _ => Err(Error::FailedToTagImage(
+                source.to_string(),
+                target.to_string(),
+            )),
+        }
+    }
+
+    fn push(&self, image: &str) -> Result<()> {
+        let (repo, tag) = split_tag(image);
+        let path = format!("/images/{}/push?tag={}", repo, percent_encode(tag));
+        match self.registry_request(&path, image)? {
+            true => Ok(()),
+            false => Err(Error::FailedToPushImage(image.to_string())),
+        }
+    }
+
+    fn save(&self, images: &[String], output: &Path) -> Result<()> {
+        let names: Vec<String> = images
+            .iter()
+            .map(|i| format!("names={}", percent_encode(i)))
+            .collect();
+        let (status, mut stream) = self.open("GET", &format!("/images/get?{}", names.join("&")))?;
+        if status != 200 {
+            return Err(Error::FailedToSaveImage(images.join(", ")));
+        }
+        io::copy(&mut stream, &mut File::create(output)?)?;
+        Ok(())
+    }
+
+    fn load(&self, input: &Path) -> Result<()> {
+        let (status, body) = self.upload("/images/load?quiet=1", input)?;
+        let body = String::from_utf8_lossy(&body);
+        log::debug!("{}", body);
+        if status != 200 || body.contains("\"error\"") {
+            return Err(Error::FailedToLoadImage(input.display().to_string()));
+        }
+        Ok(())
+    }
+
+    fn list_images(&self, filters: &[String]) -> Result<Vec<ImageSummary>> {
+        let mut by_key: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (key, value) in filters.iter().filter_map(|f| f.split_once('=')) {
+            by_key.entry(key).or_default().push(value);
+        }
+        let filters = serde_json::json!(by_key).to_string();
+        let (status, body) = self.request(
+            "GET",
+            &format!("/images/json?filters={}", percent_encode(&filters)),
+        )?;
+        if status != 200 {
+            return Err(Error::EngineRequestFailed(
+                status,
+                String::from_utf8_lossy(&body).to_string(),
+            ));
+        }
+        runtime::parse_image_list(&body)
+    }
+
+    fn remove_image(&self, image: &str) -> Result<()> {
+        let (status, _) = self.request("DELETE", &format!("/images/{}", image))?;
+        match status {
+            200 => Ok(()),
+            _ => Err(Error::FailedToRemoveImage(image.to_string())),
+        }
+    }
+
+    fn list_containers(&self, labels: &[String]) -> Result<Vec<ContainerSummary>> {
+        let filters = serde_json::json!({ "label": labels }).to_string();
+        let (status, body) = self.request(
+            "GET",
+            &format!(
+                "/containers/json?all=1&filters={}",
+                percent_encode(&filters)
+            ),
+        )?;
+        if status != 200 {
+            return Err(Error::EngineRequestFailed(
+                status,
+                String::from_utf8_lossy(&body).to_string(),
+            ));
+        }
+        runtime::parse_container_list(&body)
+    }
+
+    fn exec(&self, container: &str, user: Option<&str>, cmd: Vec<String>) -> Result<i32> {
+        let mut options = CreateExecOptions::new();
+        options.attach_stdout(true).attach_stderr(true);
+        if let Some(user) = user {
+            options.user(user.to_string());
+        }
+        for arg in cmd {
+            options.cmd(arg);
+        }
+        let exec = self.client.exec_container(container, &options)?;
+        // started by hand so that an engine refusing to stream the output is reported as an error
+        let (status, mut output) = send(
+            &self.socket,
+            "POST",
+            &format!("/exec/{}/start", exec.id),
+            &[],
+            Some(br#"{"Detach":false,"Tty":false}"#),
+        )?;
+        if status != 200 && status != 101 {
+            let (_, body) = read_body((status, output))?;
+            return Err(Error::EngineRequestFailed(
+                status,
+                String::from_utf8_lossy(&body).to_string(),
+            ));
+        }
+        demultiplex(&mut output)?;
+        let info = self.client.exec_inspect(&exec.id)?;
+        Ok(info.ExitCode.map_or(1, |code| code as i32))
+    }
+
+    fn logs(&self, container: &str, follow: bool) -> Result<i32> {
+        let stdio = self.container_stdio(container)?;
+        let (status, mut stream) = self.open(
+            "GET",
+            &format!(
+                "/containers/{}/logs?stdout=1&stderr=1&follow={}",
+                container, follow as u8
+            ),
+        )?;
+        if status != 200 {
+            return Err(Error::EngineRequestFailed(
+                status,
+                format!("logs {}", container),
+            ));
+        }
+        if stdio.tty {
+            io::copy(&mut stream, &mut io::stdout())?;
+        } else {
+            demultiplex(&mut stream)?;
+        }
+        Ok(0)
+    }
+
+    fn stop(&self, container: &str) -> Result<()> {
+        let (status, _) = self.request("POST", &format!("/containers/{}/stop", container))?;
+        match status {
+            204 | 304 => Ok(()),
+            _ => Err(Error::FailedToStopContainer(container.to_string())),
+        }
+    }
+
+    fn attach(&self, container: &str) -> Result<i32> {
+        let stdio = self.container_stdio(container)?;
+        let _signals = self.forward_signals(container)?;
+        let _terminal = if stdio.tty {
+            RawTerminal::enable()
+        } else {
+            None
+        };
+        let _ = self.hijack(container, stdio.tty, stdio.open_stdin)?.join();
+        Ok(self.client.wait_container(container)?.into_inner())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct CreatedContainer {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedContainer {
+    config: ContainerStdio,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerStdio {
+    #[serde(default)]
+    tty: bool,
+    #[serde(default)]
+    open_stdin: bool,
+}
+
+/// Sends a request and returns the response status along with the connection, positioned at the
+/// start of the response body. HTTP/1.0 is used so that the engine neither keeps the connection
+/// alive nor chunks the response.
+fn send(
+    socket: &Path,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+) -> Result<(u16, UnixStream)> {
+    let mut stream = UnixStream::connect(socket)?;
+    write!(stream, "{} {} HTTP/1.0\r\nHost: docker\r\n", method, path)?;
+    for (name, value) in headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    if let Some(body) = body {
+        write!(
+            stream,
+            "Content-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )?;
+        stream.write_all(body)?;
+    } else {
+        write!(stream, "\r\n")?;
+    }
+    let status = read_response_head(&mut stream)?;
+    Ok((status, stream))
+}
+
+fn read_body((status, mut stream): (u16, UnixStream)) -> Result<(u16, Vec<u8>)> {
+    let mut body = Vec::new();
+    stream.read_to_end(&mut body)?;
+    Ok((status, body))
+}
+
+/// Encodes a query string value, leaving only RFC 3986 unreserved characters as they are.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Reads an HTTP response status line and headers one byte at a time so that nothing past the
+/// header block is consumed, and returns the status code.
+fn read_response_head<R: Read>(stream: &mut R) -> Result<u16> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            break;
+        }
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    head.split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| Error::EngineRequestFailed(0, head.to_string()))
+}
+
+/// Splits the multiplexed stdout/stderr stream the engine sends for non-TTY containers.
+fn demultiplex<R: Read>(stream: &mut R) -> io::Result<()> {
+    let mut header = [0u8; 8];
+    loop {
+        if stream.read_exact(&mut header).is_err() {
+            return Ok(());
+        }
+        let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let mut frame = vec![0u8; size];
+        stream.read_exact(&mut frame)?;
+        match header[0] {
+            2 => io::stderr().write_all(&frame)?,
+            _ => io::stdout().write_all(&frame)?,
+        }
+    }
+}
+
+/// Puts the host terminal into raw mode for the lifetime of the value so that keystrokes are
+/// passed straight through to a container TTY.
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable() -> Option<RawTerminal> {
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return None;
+            }
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return None;
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            Some(RawTerminal { original })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod run_config_should {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn convert_from_run_args() -> Result<()> {
+        let args = strings(&[
+            "-it",
+            "--rm",
+            "--init",
+            "--name",
+            "firefox-work",
+            "-v",
+            "/tmp/.X11-unix:/tmp/.X11-unix",
+            "-e",
+            "DISPLAY=unix:0",
+            "--device",
+            "/dev/dri",
+            "--cap-add",
+            "SYS_ADMIN",
+            "--net",
+            "host",
+            "--memory",
+            "1024mb",
+            "--cpu-shares",
+            "512",
+            "--label",
+            "dfiles.app=firefox",
+            "--entrypoint",
+            "/entrypoint.bash",
+            "waynr/firefox:129.0.1",
+            "/bin/bash",
+            "-l",
+        ]);
+        assert_eq!(
+            RunConfig::try_from(args.as_slice())?,
+            RunConfig {
+                name: Some("firefox-work".to_string()),
+                image: "waynr/firefox:129.0.1".to_string(),
+                cmd: strings(&["/bin/bash", "-l"]),
+                entrypoint: Some("/entrypoint.bash".to_string()),
+                env: strings(&["DISPLAY=unix:0"]),
+                binds: strings(&["/tmp/.X11-unix:/tmp/.X11-unix"]),
+                devices: strings(&["/dev/dri"]),
+                cap_add: strings(&["SYS_ADMIN"]),
+                network_mode: Some("host".to_string()),
+                memory: Some(1024 * 1024 * 1024),
+                cpu_shares: Some(512),
+                labels: strings(&["dfiles.app=firefox"]),
+                init: true,
+                remove: true,
+                detach: false,
+                tty: true,
+                interactive: true,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unknown_flags() {
+        let args = strings(&["--privileged", "waynr/firefox"]);
+        assert!(RunConfig::try_from(args.as_slice()).is_err());
+    }
+}
+
+#[cfg(test)]
+mod engine_should {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Serves just enough of the engine API to run a container that exits with status 3.
+    fn fake_engine(socket: &Path, requests: Arc<Mutex<Vec<String>>>) {
+        let listener = UnixListener::bind(socket).unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let requests = requests.clone();
+                thread::spawn(move || serve(stream.unwrap(), requests));
+            }
+        });
+    }
+
+    fn serve(stream: UnixStream, requests: Arc<Mutex<Vec<String>>>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                return;
+            }
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = v.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            // dockworker prefixes request paths with an extra slash
+            let words: Vec<&str> = request_line.split_whitespace().collect();
+            let (path, query) = words[1].split_once('?').unwrap_or((words[1], ""));
+            let path = path.replacen("//", "/", 1);
+            let (method, path) = (words[0], path.as_str());
+            requests
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", method, path));
+
+            let (status, body) = match (method, path) {
+                ("POST", "/containers/create") => ("201 Created", r#"{"Id":"abc","Warnings":[]}"#),
+                ("POST", "/containers/abc/attach") => {
+                    stream
+                        .write_all(
+                            b"HTTP/1.1 101 UPGRADED\r\nConnection: Upgrade\r\nUpgrade: tcp\r\n\r\n",
+                        )
+                        .unwrap();
+                    return;
+                }
+                ("POST", "/containers/abc/wait") => ("200 OK", r#"{"StatusCode":3}"#),
+                ("GET", "/images/waynr/firefox:1/json") => {
+                    ("404 Not Found", r#"{"message":"no such image"}"#)
+                }
+                ("POST", "/images/waynr/firefox:1/tag")
+                | ("POST", "/images/localhost:5000/waynr/firefox:1/tag") => ("201 Created", ""),
+                ("POST", "/images/localhost:5000/waynr/firefox/push")
+                | ("POST", "/images/create") => ("200 OK", r#"{"status":"Done"}"#),
+                ("GET", "/images/get") => ("200 OK", "image layers"),
+                ("GET", "/images/json")
+                    if query
+                        == format!(
+                            "filters={}",
+                            percent_encode(r#"{"label":["dfiles.app=firefox"]}"#)
+                        ) =>
+                {
+                    (
+                        "200 OK",
+                        r#"[{"Id":"sha256:a","RepoTags":null,"Created":1}]"#,
+                    )
+                }
+                ("GET", "/images/json") => ("200 OK", "[]"),
+                ("POST", "/images/load") => ("200 OK", r#"{"stream":"Loaded image"}"#),
+                ("POST", "/containers/abc/exec") => ("201 Created", r#"{"Id":"e1"}"#),
+                ("POST", "/exec/e1/start") => ("404 Not Found", r#"{"message":"no such exec"}"#),
+                _ => ("204 No Content", ""),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            if request_line.trim_end().ends_with("HTTP/1.0") {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn run_a_container_to_completion() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("docker.sock");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        fake_engine(&socket, requests.clone());
+
+        let engine = Engine::connect_with_unix(&socket)?;
+        let args: Vec<String> = vec![
+            "--rm",
+            "--init",
+            "--name",
+            "firefox-default",
+            "waynr/firefox:1",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        assert_eq!(engine.run(args)?, 3);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                "POST /containers/create",
+                "POST /containers/abc/attach",
+                "POST /containers/abc/start",
+                "POST /containers/abc/wait",
+                "DELETE /containers/abc",
+            ]
+        );
+        assert!(engine.inspect_image("waynr/firefox:1")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn report_exec_failures() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("docker.sock");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        fake_engine(&socket, requests.clone());
+
+        let engine = Engine::connect_with_unix(&socket)?;
+        assert!(matches!(
+            engine.exec("abc", None, vec![String::from("firefox")]),
+            Err(Error::EngineRequestFailed(404, _))
+        ));
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["POST /containers/abc/exec", "POST /exec/e1/start"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn list_images_by_label() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("docker.sock");
+        fake_engine(&socket, Arc::new(Mutex::new(Vec::new())));
+
+        let engine = Engine::connect_with_unix(&socket)?;
+        let images = engine.list_images(&[String::from("label=dfiles.app=firefox")])?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].id, "sha256:a");
+        assert!(images[0].tags.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn push_and_pull_through_a_registry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("docker.sock");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        fake_engine(&socket, requests.clone());
+
+        let engine = Engine::connect_with_unix(&socket)?;
+        engine.tag("waynr/firefox:1", "localhost:5000/waynr/firefox:1")?;
+        engine.push("localhost:5000/waynr/firefox:1")?;
+        engine.pull("localhost:5000/waynr/firefox:1")?;
+        engine.tag("localhost:5000/waynr/firefox:1", "waynr/firefox:1")?;
+        assert!(engine.push("localhost:5000/waynr/chrome:1").is_err());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                "POST /images/waynr/firefox:1/tag",
+                "POST /images/localhost:5000/waynr/firefox/push",
+                "POST /images/create",
+                "POST /images/localhost:5000/waynr/firefox:1/tag",
+                "POST /images/localhost:5000/waynr/chrome/push",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn save_and_load_images() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("docker.sock");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        fake_engine(&socket, requests.clone());
+
+        let engine = Engine::connect_with_unix(&socket)?;
+        let saved = dir.path().join("saved.tar");
+        engine.save(&[String::from("waynr/firefox:1")], &saved)?;
+        assert_eq!(std::fs::read(&saved)?, b"image layers");
+        engine.load(&saved)?;
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["GET /images/get", "POST /images/load"]
+        );
+        Ok(())
+    }
+}
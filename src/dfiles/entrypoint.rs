@@ -5,14 +5,22 @@ use std::path::{Path, PathBuf};
 
 use super::aspects;
 use super::error::{Error, Result};
+use super::packages::PackageManager;
+use super::phase::{self, Phase, Placed};
 
+/// A block of the entrypoint script, placed and deduplicated the same way as
+/// [`DockerfileSnippet`](aspects::DockerfileSnippet)s.
+#[derive(Default, PartialEq)]
 pub struct ScriptSnippet {
     pub description: String,
-    pub order: u16,
+    pub phase: Phase,
     pub snippet: String,
+    pub after: Vec<String>,
+    pub before: Vec<String>,
+    pub key: Option<String>,
 }
 
-const ENTRYPOINT_SETUP_SCRIPT: &str = "entrypoint.bash";
+pub(crate) const ENTRYPOINT_SETUP_SCRIPT: &str = "entrypoint.bash";
 
 /// Generates command line arguments to be used in `docker run` calls on the host system.
 fn run_args(tmpdir: &Path) -> Result<Vec<String>> {
@@ -33,10 +41,15 @@ fn run_args(tmpdir: &Path) -> Result<Vec<String>> {
     Ok(args)
 }
 
-fn write_script(tmpdir: &Path, mut scripts: Vec<ScriptSnippet>) -> Result<PathBuf> {
-    let path = tmpdir.join(ENTRYPOINT_SETUP_SCRIPT);
-    std::fs::create_dir_all(path.parent().unwrap())?;
+/// The result of setting up the entrypoint script for a container run.
+pub(crate) struct Entrypoint {
+    /// Arguments to add to the container runtime `run` invocation.
+    pub run_args: Vec<String>,
+    /// The rendered script, if any aspect contributed entrypoint snippets.
+    pub script: Option<String>,
+}
 
+fn render_script(scripts: Vec<ScriptSnippet>) -> Result<String> {
     let mut buffer = String::new();
 
     writeln!(
@@ -45,7 +58,6 @@ fn write_script(tmpdir: &Path, mut scripts: Vec<ScriptSnippet>) -> Result<PathBu
 USER=root"#
     )?;
 
-    scripts.sort_by(|a, b| a.order.partial_cmp(&b.order).unwrap());
     for script in scripts {
         for line in script.description.lines() {
             writeln!(buffer, "### {0}", line)?;
@@ -53,8 +65,18 @@ USER=root"#
 
         writeln!(buffer, "{0}", script.snippet)?;
     }
-    writeln!(buffer, "\n# execute whatever command was specified")?;
-    write!(buffer, "sudo --user $USER $@")?;
+    writeln!(
+        buffer,
+        "\n# replace this shell with whatever command was specified so it gets signals"
+    )?;
+    write!(buffer, "exec sudo --user $USER \"$@\"")?;
+
+    Ok(buffer)
+}
+
+fn write_script(tmpdir: &Path, buffer: &str) -> Result<PathBuf> {
+    let path = tmpdir.join(ENTRYPOINT_SETUP_SCRIPT);
+    std::fs::create_dir_all(path.parent().unwrap())?;
 
     let mut file = std::fs::File::create(&path)?;
     let mut perms = file.metadata()?.permissions();
@@ -69,27 +91,56 @@ USER=root"#
     Ok(path)
 }
 
+/// Collects the entrypoint snippets of all aspects, for a base image using `package_manager`, in
+/// the order they should run.
+pub(crate) fn arrange(
+    aspects: &[Box<dyn aspects::ContainerAspect>],
+    package_manager: PackageManager,
+) -> Result<Vec<ScriptSnippet>> {
+    let mut placed = Vec::new();
+    for aspect in aspects {
+        for mut snippet in aspect.entrypoint_snippets_for(package_manager)? {
+            placed.push(Placed {
+                aspect: aspect.name(),
+                key: snippet.key.clone(),
+                phase: snippet.phase,
+                after: std::mem::take(&mut snippet.after),
+                before: std::mem::take(&mut snippet.before),
+                item: snippet,
+            });
+        }
+    }
+    Ok(phase::arrange(placed)?
+        .into_iter()
+        .map(|p| p.item)
+        .collect())
+}
+
 pub(crate) fn setup(
     tmpdir: &Path,
     aspects: &[Box<dyn aspects::ContainerAspect>],
-) -> Result<Vec<String>> {
-    let scripts: Vec<ScriptSnippet> = aspects
-        .iter()
-        .map(|a| a.entrypoint_snippets())
-        .collect::<Result<Vec<Vec<_>>>>()?
-        .into_iter()
-        .flatten()
-        .collect();
+    package_manager: PackageManager,
+) -> Result<Entrypoint> {
+    let scripts = arrange(aspects, package_manager)?;
 
     if scripts.is_empty() {
-        return Ok(Vec::<String>::new());
+        return Ok(Entrypoint {
+            run_args: Vec::new(),
+            script: None,
+        });
     }
 
-    write_script(tmpdir, scripts)?;
+    let script = render_script(scripts)?;
+    write_script(tmpdir, &script)?;
     log::trace!("entrypoint tmpdir: {}", tmpdir.display());
-    run_args(tmpdir)
+    Ok(Entrypoint {
+        run_args: run_args(tmpdir)?,
+        script: Some(script),
+    })
 }
 
+/// Adds the container user to the group `group_name`, using the host's gid for it. The snippet is
+/// keyed by group so several aspects can ask for the same group.
 pub fn group_setup(group_name: &str) -> Result<ScriptSnippet> {
     let name = match users::get_current_username() {
         Some(n) => n.to_string_lossy().to_string(),
@@ -100,15 +151,35 @@ pub fn group_setup(group_name: &str) -> Result<ScriptSnippet> {
         None => return Err(Error::MissingGroup(group_name.to_string())),
     };
     Ok(ScriptSnippet {
-        order: 5,
+        phase: Phase::SystemPackages,
         description: "configure video group for container user".to_string(),
         snippet: format!(
-            r#"adduser {user} {group_name}
+            r#"usermod --append --groups {group_name} {user}
 groupmod -g {video_gid} {group_name}
         "#,
             user = name,
             group_name = video_group.name().to_string_lossy(),
             video_gid = video_group.gid(),
         ),
+        key: Some(format!("group:{}", group_name)),
+        ..Default::default()
     })
 }
+
+#[cfg(test)]
+mod entrypoint_should {
+    use super::*;
+
+    #[test]
+    fn exec_the_command_so_it_receives_signals() -> Result<()> {
+        let script = render_script(vec![ScriptSnippet {
+            description: String::from("Timezone"),
+            snippet: String::from("export TZ=UTC"),
+            ..Default::default()
+        }])?;
+        assert!(script.starts_with("#!/usr/bin/env bash\nUSER=root\n"));
+        assert!(script.contains("### Timezone\nexport TZ=UTC\n"));
+        assert!(script.ends_with("\nexec sudo --user $USER \"$@\""));
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use dockworker;
 use thiserror;
 
+use super::phase::Phase;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(thiserror::Error, Debug)]
@@ -20,6 +22,9 @@ pub enum Error {
     #[error("failed to add file to archive")]
     FailedToAddFileToArchive { source: std::io::Error },
 
+    #[error("build context path `{0}` leaves the context directory")]
+    InvalidContextPath(String),
+
     #[error("could not identify user `{0:?}`")]
     MissingUser(String),
 
@@ -35,6 +40,83 @@ pub enum Error {
     #[error("invalid timezone `{0}`")]
     InvalidTimezone(String),
 
+    #[error("profile `{profile}` can't set {values}, the app image is shared by all profiles; set it in the app config instead")]
+    ProfileBuildConfig { profile: String, values: String },
+
+    #[error("invalid container runtime `{0}`")]
+    InvalidRuntime(String),
+
+    #[error("failed to spawn `{program}`")]
+    FailedToSpawn {
+        program: String,
+        source: std::io::Error,
+    },
+
+    #[error("`{0}` exited unsuccessfully: {1}")]
+    RuntimeCommandFailed(String, std::process::ExitStatus),
+
+    #[error("unsupported container run argument `{0}`")]
+    UnsupportedRunArg(String),
+
+    #[error("invalid value `{1}` for container run argument `{0}`")]
+    InvalidRunArg(String, String),
+
+    #[error("unsupported DOCKER_HOST `{0}`, only unix sockets can be used")]
+    UnsupportedDockerHost(String),
+
+    #[error("docker engine request failed with status {0}: {1}")]
+    EngineRequestFailed(u16, String),
+
+    #[error("failed to list containers")]
+    FailedToListContainers,
+
+    #[error("failed to stop container `{0}`")]
+    FailedToStopContainer(String),
+
+    #[error("no {app} container found for profile `{profile}`")]
+    NoContainerFound { app: String, profile: String },
+
+    #[error("invalid build argument `{0}`, expected KEY=VAL")]
+    InvalidBuildArg(String),
+
+    #[error("invalid sha256 checksum `{0}`, expected 64 hex digits")]
+    InvalidChecksum(String),
+
+    #[error("build failed at `{step}`: {message}")]
+    BuildFailed { step: String, message: String },
+
+    #[error("image `{0}` not found locally")]
+    ImageNotFound(String),
+
+    #[error("{aspect} ({phase}) cannot be placed {relation} {target} ({target_phase})")]
+    ConflictingSnippetOrder {
+        aspect: String,
+        phase: Phase,
+        relation: &'static str,
+        target: String,
+        target_phase: Phase,
+    },
+
+    #[error("snippet ordering constraints form a cycle between: {0}")]
+    SnippetOrderCycle(String),
+
+    #[error("{aspect} and {other} add {phase} snippets in no particular order, declare `after` or `before` between them")]
+    AmbiguousSnippetOrder {
+        phase: Phase,
+        aspect: String,
+        other: String,
+    },
+
+    #[error("{aspect} and {other} contribute different snippets for `{key}`")]
+    ConflictingSnippets {
+        key: String,
+        aspect: String,
+        other: String,
+    },
+
+    #[error("failed to inspect image")]
+    FailedToInspectImage,
+
     #[error("could not identify directory")]
     MissingDirectory,
 
@@ -53,6 +135,67 @@ pub enum Error {
     #[error("failed to load config from file")]
     FailedToLoadConfig,
 
+    #[error("failed to save lockfile")]
+    FailedToSaveLockfile,
+
+    #[error("failed to load lockfile")]
+    FailedToLoadLockfile,
+
+    #[error("app is not locked, run the `lock` subcommand first")]
+    MissingLockfile,
+
+    #[error("build inputs drifted from the lockfile:\n{0}")]
+    LockfileDrift(String),
+
+    #[error("`{0}` is not in the lockfile, run the `lock` subcommand again")]
+    UnlockedInput(String),
+
+    #[error("failed to download `{0}`")]
+    FailedToDownload(String),
+
+    #[error("failed to pull image `{0}`")]
+    FailedToPullImage(String),
+
+    #[error("failed to push image `{0}`")]
+    FailedToPushImage(String),
+
+    #[error("failed to tag image `{0}` as `{1}`")]
+    FailedToTagImage(String, String),
+
+    #[error("invalid registry `{0}`, expected a host and optional namespace such as localhost:5000/waynr")]
+    InvalidRegistry(String),
+
+    #[error("failed to load docker config")]
+    FailedToLoadDockerConfig,
+
+    #[error("failed to list images")]
+    FailedToListImages,
+
+    #[error("failed to remove image `{0}`")]
+    FailedToRemoveImage(String),
+
+    #[error("failed to save image `{0}`")]
+    FailedToSaveImage(String),
+
+    #[error("failed to load image from `{0}`")]
+    FailedToLoadImage(String),
+
+    #[error("`{0}` is not a dfiles image export")]
+    InvalidExport(String),
+
+    #[error("checksum mismatch for `{path}`: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("export contains an image of `{app}`, not `{expected}`")]
+    ExportForOtherApp { app: String, expected: String },
+
+    #[error("no digest found for image `{0}`")]
+    MissingImageDigest(String),
+
     #[error("local entrypoint path must exist")]
     LocalEntrypointPathMustExist,
 
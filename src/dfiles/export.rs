@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder};
+
+use super::error::{Error, Result};
+
+const MANIFEST: &str = "manifest.json";
+const IMAGE: &str = "image.tar";
+
+/// Describes the image in an export: which app and tags it belongs to and what it was built from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub app: String,
+    pub tags: Vec<String>,
+    pub image_id: String,
+    /// The dfiles labels recorded on the image.
+    pub labels: BTreeMap<String, String>,
+    /// Names of the aspects the image was built with.
+    pub aspects: Vec<String>,
+    /// Checksum of the image archive saved by the container runtime.
+    pub image_sha256: String,
+}
+
+/// Returns the hex sha256 checksum of the file at `path`.
+pub(crate) fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Writes an export to `output`: the manifest followed by the image archive saved by the
+/// container runtime.
+pub(crate) fn write(output: &Path, manifest: &Manifest, image: &Path) -> Result<()> {
+    let json = serde_json::to_vec_pretty(manifest).map_err(io::Error::from)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    let mut archive = Builder::new(File::create(output)?);
+    archive.append_data(&mut header, MANIFEST, json.as_slice())?;
+    archive.append_path_with_name(image, IMAGE)?;
+    archive.finish()?;
+    Ok(())
+}
+
+/// Unpacks the export at `input` into `dir` and returns its manifest along with the path of the
+/// image archive, once the archive has been checked against the manifest's checksum.
+pub(crate) fn read(input: &Path, dir: &Path) -> Result<(Manifest, PathBuf)> {
+    let invalid = || Error::InvalidExport(input.display().to_string());
+    let mut manifest = None;
+    let image = dir.join(IMAGE);
+    let mut found_image = false;
+
+    for entry in Archive::new(File::open(input)?).entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        if path == Path::new(MANIFEST) {
+            manifest =
+                Some(serde_json::from_reader::<_, Manifest>(&mut entry).map_err(|_| invalid())?);
+        } else if path == Path::new(IMAGE) {
+            io::copy(&mut entry, &mut File::create(&image)?)?;
+            found_image = true;
+        }
+    }
+
+    let manifest = manifest.ok_or_else(invalid)?;
+    if !found_image {
+        return Err(invalid());
+    }
+    verify(&image, &manifest.image_sha256)?;
+    Ok((manifest, image))
+}
+
+/// Path of the `sha256sum`-style checksum file written next to an export.
+pub(crate) fn checksum_path(export: &Path) -> PathBuf {
+    let mut name = export.as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// Writes the checksum of `export` next to it and returns the checksum.
+pub(crate) fn write_checksum(export: &Path) -> Result<String> {
+    let sha256 = sha256_file(export)?;
+    let name = export
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fs::write(checksum_path(export), format!("{}  {}\n", sha256, name))?;
+    Ok(sha256)
+}
+
+/// Reads the checksum written next to `export`, if there is one.
+pub(crate) fn read_checksum(export: &Path) -> Result<Option<String>> {
+    let path = checksum_path(export);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(fs::read_to_string(path)?
+        .split_whitespace()
+        .next()
+        .map(String::from))
+}
+
+/// Checks that the file at `path` has the `expected` sha256 checksum.
+pub(crate) fn verify(path: &Path, expected: &str) -> Result<()> {
+    let actual = sha256_file(path)?;
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(Error::ChecksumMismatch {
+            path: path.display().to_string(),
+            expected: expected.trim().to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod export_should {
+    use super::*;
+
+    fn manifest(image_sha256: String) -> Manifest {
+        Manifest {
+            app: String::from("firefox"),
+            tags: vec![String::from("waynr/firefox:1")],
+            image_id: String::from("sha256:0123"),
+            labels: BTreeMap::from([(
+                String::from("dfiles.aspects"),
+                String::from("Debian,firefox"),
+            )]),
+            aspects: vec![String::from("Debian"), String::from("firefox")],
+            image_sha256,
+        }
+    }
+
+    #[test]
+    fn round_trip_images_with_their_manifest() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let image = dir.path().join("saved.tar");
+        fs::write(&image, b"image layers")?;
+        let export = dir.path().join("firefox-image.tar");
+        write(&export, &manifest(sha256_file(&image)?), &image)?;
+
+        let sha256 = write_checksum(&export)?;
+        assert_eq!(read_checksum(&export)?, Some(sha256.clone()));
+        verify(&export, &sha256)?;
+
+        let unpacked = tempfile::tempdir()?;
+        let (read_manifest, read_image) = read(&export, unpacked.path())?;
+        assert_eq!(read_manifest, manifest(sha256_file(&image)?));
+        assert_eq!(fs::read(read_image)?, b"image layers");
+        Ok(())
+    }
+
+    #[test]
+    fn reject_corrupt_exports() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let image = dir.path().join("saved.tar");
+        fs::write(&image, b"image layers")?;
+        let export = dir.path().join("firefox-image.tar");
+        write(&export, &manifest(sha256_file(&image)?), &image)?;
+        assert!(matches!(
+            verify(&export, &"0".repeat(64)),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+
+        write(&export, &manifest("0".repeat(64)), &image)?;
+        let unpacked = tempfile::tempdir()?;
+        assert!(matches!(
+            read(&export, unpacked.path()),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+
+        fs::write(&export, b"not a tarball")?;
+        assert!(read(&export, unpacked.path()).is_err());
+        Ok(())
+    }
+}
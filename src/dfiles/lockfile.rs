@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::aspects::Download;
+use super::dirs;
+use super::dockerfile::Instruction;
+use super::error::{Error, Result};
+use super::runtime::split_tag;
+
+/// Build inputs resolved by the `lock` subcommand: the digest of each base image and the sha256
+/// checksum of each file downloaded with `ADD`. Apps with a lockfile are built from exactly
+/// these inputs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Lockfile {
+    /// Image references, e.g. `debian:bookworm`, mapped to their `sha256:...` digest.
+    #[serde(default)]
+    pub images: BTreeMap<String, String>,
+    /// Download URLs mapped to the hex sha256 checksum of their contents.
+    #[serde(default)]
+    pub downloads: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    fn path(application: &str) -> Result<PathBuf> {
+        Ok(dirs::get_config_dir(Some(application), None)?.join("lock.yaml"))
+    }
+
+    /// Loads the app's lockfile, or `None` if the app hasn't been locked.
+    pub fn load(application: &str) -> Result<Option<Lockfile>> {
+        let path = Lockfile::path(application)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let yaml = fs::read_to_string(path)?;
+        let lock = serde_yaml::from_str(&yaml).map_err(|_| Error::FailedToLoadLockfile)?;
+        Ok(Some(lock))
+    }
+
+    pub fn save(&self, application: &str) -> Result<()> {
+        let path = Lockfile::path(application)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let yaml = serde_yaml::to_string(self).map_err(|_| Error::FailedToSaveLockfile)?;
+        fs::write(path, yaml)?;
+        Ok(())
+    }
+
+    /// Rewrites an instruction to use the locked inputs: `FROM` pins the image digest and `ADD`
+    /// of a URL becomes a download verified against the locked checksum. Base images and
+    /// downloads missing from the lockfile are an error rather than being left unchecked.
+    pub fn pin(&self, instruction: &Instruction) -> Result<Instruction> {
+        match instruction {
+            Instruction::From(image) if !image.contains('@') => match self.images.get(image) {
+                Some(digest) => Ok(Instruction::From(format!(
+                    "{}@{}",
+                    split_tag(image).0,
+                    digest
+                ))),
+                None => Err(Error::UnlockedInput(image.clone())),
+            },
+            Instruction::Add { src, dest } if is_url(src) => match self.downloads.get(src) {
+                Some(sha256) => Ok(Instruction::Run(
+                    Download::new(src, sha256, &destination(src, dest))?.commands(),
+                )),
+                None => Err(Error::UnlockedInput(src.clone())),
+            },
+            _ => Ok(instruction.clone()),
+        }
+    }
+
+    /// Describes every input of `resolved` that this lockfile doesn't cover or records
+    /// differently, and every locked input that is no longer used.
+    pub fn drift(&self, resolved: &Lockfile) -> Vec<String> {
+        let compare = |locked: &BTreeMap<String, String>, current: &BTreeMap<String, String>| {
+            let mut drift: Vec<String> = current
+                .iter()
+                .filter_map(|(input, value)| match locked.get(input) {
+                    None => Some(format!("{} is not locked", input)),
+                    Some(l) if l != value => {
+                        Some(format!("{} changed from {} to {}", input, l, value))
+                    }
+                    Some(_) => None,
+                })
+                .collect();
+            drift.extend(
+                locked
+                    .keys()
+                    .filter(|input| !current.contains_key(*input))
+                    .map(|input| format!("{} is locked but no longer used", input)),
+            );
+            drift
+        };
+        let mut drift = compare(&self.images, &resolved.images);
+        drift.extend(compare(&self.downloads, &resolved.downloads));
+        drift
+    }
+}
+
+/// Whether an `ADD` source is fetched over the network rather than taken from the build context.
+pub(crate) fn is_url(src: &str) -> bool {
+    src.starts_with("https://") || src.starts_with("http://")
+}
+
+/// Downloads `url` on the host and returns the hex sha256 checksum of its contents.
+pub(crate) fn sha256_of_url(url: &str) -> Result<String> {
+    let output = Command::new("curl")
+        .args(["-fsSL", url])
+        .output()
+        .map_err(|e| Error::FailedToSpawn {
+            program: String::from("curl"),
+            source: e,
+        })?;
+    if !output.status.success() {
+        return Err(Error::FailedToDownload(url.to_string()));
+    }
+    Ok(format!("{:x}", Sha256::digest(&output.stdout)))
+}
+
+/// The path `ADD` would write `src` to; a directory destination keeps the URL's file name.
+fn destination(src: &str, dest: &str) -> String {
+    let name = src.rsplit('/').next().unwrap_or_default();
+    match dest {
+        "." | "./" => name.to_string(),
+        d if d.ends_with('/') => format!("{}{}", d, name),
+        d => d.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod lockfile_should {
+    use super::*;
+
+    const SHA256: &str = "c2c80ed7990470edbcbbcedf3747038ece44786469b278af282f86fac5e50b38";
+
+    fn lock() -> Lockfile {
+        Lockfile {
+            images: BTreeMap::from([(
+                String::from("localhost:5000/debian:bookworm"),
+                String::from("sha256:0123"),
+            )]),
+            downloads: BTreeMap::from([(
+                String::from("https://example.com/app.tar.bz2"),
+                SHA256.to_string(),
+            )]),
+        }
+    }
+
+    #[test]
+    fn pin_locked_inputs() -> Result<()> {
+        let lock = lock();
+        assert_eq!(
+            lock.pin(&Instruction::From(String::from(
+                "localhost:5000/debian:bookworm"
+            )))?,
+            Instruction::From(String::from("localhost:5000/debian@sha256:0123"))
+        );
+        assert_eq!(
+            lock.pin(&Instruction::Add {
+                src: String::from("https://example.com/app.tar.bz2"),
+                dest: String::from("./"),
+            })?
+            .to_string(),
+            format!(
+                "RUN curl -fsSL https://example.com/app.tar.bz2 -o app.tar.bz2 \\\n  && echo '{}  app.tar.bz2' | sha256sum -c -",
+                SHA256
+            )
+        );
+
+        let pinned = Instruction::From(String::from("debian@sha256:4567"));
+        assert_eq!(lock.pin(&pinned)?, pinned);
+        assert!(matches!(
+            lock.pin(&Instruction::From(String::from("debian:trixie"))),
+            Err(Error::UnlockedInput(_))
+        ));
+        assert!(matches!(
+            lock.pin(&Instruction::Add {
+                src: String::from("https://example.com/other.tar.bz2"),
+                dest: String::from("./"),
+            }),
+            Err(Error::UnlockedInput(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn report_drifted_inputs() {
+        let mut resolved = lock();
+        assert!(lock().drift(&resolved).is_empty());
+
+        resolved.images.insert(
+            String::from("localhost:5000/debian:bookworm"),
+            String::from("sha256:4567"),
+        );
+        resolved.downloads.insert(
+            String::from("https://example.com/other"),
+            SHA256.to_string(),
+        );
+        resolved.downloads.remove("https://example.com/app.tar.bz2");
+        assert_eq!(
+            lock().drift(&resolved),
+            vec![
+                "localhost:5000/debian:bookworm changed from sha256:0123 to sha256:4567",
+                "https://example.com/other is not locked",
+                "https://example.com/app.tar.bz2 is locked but no longer used",
+            ]
+        );
+    }
+}
@@ -3,6 +3,16 @@ pub mod config;
 pub mod containermanager;
 pub mod dirs;
 pub mod docker;
+pub mod dockerfile;
+pub mod engine;
 pub mod entrypoint;
 pub mod error;
+pub mod export;
+pub mod lockfile;
 pub mod logging;
+pub mod packages;
+pub mod phase;
+pub mod podman;
+pub mod registry;
+pub mod runtime;
+mod signals;
@@ -0,0 +1,140 @@
+use super::dockerfile::Instruction;
+
+/// The package manager of a base image. Aspects name the packages they need using Debian package
+/// names, which the package manager translates for its own distro.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PackageManager {
+    #[default]
+    Apt,
+    Dnf,
+    Apk,
+}
+
+/// Debian package names along with their Fedora and Alpine equivalents, where those exist.
+const NAMES: &[(&str, Option<&str>, Option<&str>)] = &[
+    ("dbus-x11", Some("dbus-x11"), Some("dbus-x11")),
+    ("firefox-esr", Some("firefox"), Some("firefox-esr")),
+    ("fonts-symbola", Some("gdouros-symbola-fonts"), None),
+    (
+        "hicolor-icon-theme",
+        Some("hicolor-icon-theme"),
+        Some("hicolor-icon-theme"),
+    ),
+    ("libasound2", Some("alsa-lib"), Some("alsa-lib")),
+    (
+        "libavcodec-extra",
+        Some("libavcodec-free"),
+        Some("ffmpeg-libavcodec"),
+    ),
+    (
+        "libcanberra-gtk*",
+        Some("libcanberra-gtk3"),
+        Some("libcanberra-gtk3"),
+    ),
+    ("libegl1", Some("mesa-libEGL"), Some("mesa-egl")),
+    ("libgbm1", Some("mesa-libgbm"), Some("mesa-gbm")),
+    ("libgl1", Some("mesa-libGL"), Some("mesa-gl")),
+    (
+        "libgl1-mesa-dri",
+        Some("mesa-dri-drivers"),
+        Some("mesa-dri-gallium"),
+    ),
+    ("libgl1-mesa-glx", Some("mesa-libGL"), Some("mesa-gl")),
+    ("libgtk-3-0", Some("gtk3"), Some("gtk+3.0")),
+    ("libpango1.0-0", Some("pango"), Some("pango")),
+    ("libpci3", Some("pciutils-libs"), Some("pciutils-libs")),
+    ("libpciaccess0", Some("libpciaccess"), Some("libpciaccess")),
+    ("libpulse0", Some("pulseaudio-libs"), Some("libpulse")),
+    ("libv4l-0", Some("libv4l"), Some("v4l-utils-libs")),
+    ("libxshmfence1", Some("libxshmfence"), Some("libxshmfence")),
+    ("libxt6", Some("libXt"), Some("libxt")),
+    ("libxtst6", Some("libXtst"), Some("libxtst")),
+    (
+        "openjdk-11-jre",
+        Some("java-11-openjdk"),
+        Some("openjdk11-jre"),
+    ),
+    ("xdg-utils", Some("xdg-utils"), Some("xdg-utils")),
+];
+
+impl PackageManager {
+    /// Translates a Debian package name for this package manager's distro. Names without a known
+    /// translation are passed through unchanged; `None` means the package isn't available.
+    pub fn package_name<'a>(&self, name: &'a str) -> Option<&'a str> {
+        let entry = NAMES.iter().find(|(debian, _, _)| *debian == name);
+        match (self, entry) {
+            (PackageManager::Apt, _) | (_, None) => Some(name),
+            (PackageManager::Dnf, Some((_, fedora, _))) => *fedora,
+            (PackageManager::Apk, Some((_, _, alpine))) => *alpine,
+        }
+    }
+
+    /// Installs the given packages in a single layer, cleaning up package caches afterwards.
+    pub fn install(&self, packages: &[&str]) -> Instruction {
+        Instruction::Install {
+            manager: *self,
+            packages: packages.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    /// The shell commands of an [`install`](PackageManager::install) layer.
+    pub fn install_commands(&self, packages: &[String]) -> Vec<String> {
+        let list = |command: &str| {
+            let mut install = command.to_string();
+            for package in packages {
+                install.push_str(" \\\n    ");
+                install.push_str(package);
+            }
+            install
+        };
+        match self {
+            PackageManager::Apt => vec![
+                String::from("apt-get update"),
+                list("apt-get install -y --no-install-recommends"),
+                String::from("apt-get purge --autoremove"),
+                String::from("rm -rf /var/lib/apt/lists/*"),
+                String::from("rm -rf /src/*.deb"),
+            ],
+            PackageManager::Dnf => vec![
+                list("dnf install -y --setopt=install_weak_deps=False"),
+                String::from("dnf clean all"),
+            ],
+            PackageManager::Apk => vec![list("apk add --no-cache")],
+        }
+    }
+}
+
+#[cfg(test)]
+mod packages_should {
+    use super::*;
+
+    #[test]
+    fn translate_debian_package_names() {
+        assert_eq!(
+            PackageManager::Apt.package_name("libpulse0"),
+            Some("libpulse0")
+        );
+        assert_eq!(
+            PackageManager::Dnf.package_name("libpulse0"),
+            Some("pulseaudio-libs")
+        );
+        assert_eq!(
+            PackageManager::Apk.package_name("libegl1"),
+            Some("mesa-egl")
+        );
+        assert_eq!(PackageManager::Apk.package_name("fonts-symbola"), None);
+        assert_eq!(PackageManager::Dnf.package_name("sudo"), Some("sudo"));
+    }
+
+    #[test]
+    fn install_packages_in_one_layer() {
+        assert_eq!(
+            PackageManager::Dnf.install(&["gtk3", "pango"]).to_string(),
+            "RUN dnf install -y --setopt=install_weak_deps=False \\\n    gtk3 \\\n    pango \\\n  && dnf clean all"
+        );
+        assert_eq!(
+            PackageManager::Apk.install(&["bash"]).to_string(),
+            "RUN apk add --no-cache \\\n    bash"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{Error, Result};
+
+/// Well-known stages of the generated Dockerfile and entrypoint script. Snippets are placed in
+/// phase order; within a phase, snippets of different aspects have to be ordered by
+/// `after`/`before` constraints on other aspects.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    /// The base image, or creating the container user.
+    Base,
+    /// Distro packages and host service integration shared by apps.
+    SystemPackages,
+    /// Locale and timezone setup.
+    Locale,
+    /// Installing and configuring the app itself.
+    #[default]
+    AppInstall,
+    /// Anything that must come last, such as image labels.
+    Finalize,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Base => "base",
+            Phase::SystemPackages => "system-packages",
+            Phase::Locale => "locale",
+            Phase::AppInstall => "app-install",
+            Phase::Finalize => "finalize",
+        };
+        f.pad(name)
+    }
+}
+
+/// Constraint target standing for the snippets of every other aspect in the same phase.
+pub const EVERY_ASPECT: &str = "*";
+
+/// A snippet along with the aspect that contributed it and where it wants to be placed.
+pub(crate) struct Placed<T> {
+    pub aspect: String,
+    pub key: Option<String>,
+    pub phase: Phase,
+    pub after: Vec<String>,
+    pub before: Vec<String>,
+    pub item: T,
+}
+
+/// Drops snippets whose key was already contributed by an earlier snippet. Snippets sharing a key
+/// must be identical.
+fn dedupe<T: PartialEq>(placed: Vec<Placed<T>>) -> Result<Vec<Placed<T>>> {
+    let mut kept: Vec<Placed<T>> = Vec::with_capacity(placed.len());
+    for p in placed {
+        let first = match &p.key {
+            Some(key) => kept.iter().find(|k| k.key.as_ref() == Some(key)),
+            None => None,
+        };
+        match first {
+            None => kept.push(p),
+            Some(first)
+                if first.phase == p.phase
+                    && first.after == p.after
+                    && first.before == p.before
+                    && first.item == p.item => {}
+            Some(first) => {
+                return Err(Error::ConflictingSnippets {
+                    key: p.key.unwrap_or_default(),
+                    aspect: first.aspect.clone(),
+                    other: p.aspect,
+                })
+            }
+        }
+    }
+    Ok(kept)
+}
+
+/// Orders snippets by phase and then by their `after`/`before` constraints. An aspect's own
+/// snippets keep the order it gave them in, but snippets of different aspects in the same phase
+/// must be ordered by constraints, directly or through other snippets; leaving the order to the
+/// order aspects were given in is an error. Snippets sharing a key are only kept once.
+/// Constraints naming aspects that contributed no snippets are ignored. A constraint that
+/// contradicts phase order, or a set of constraints that form a cycle, is an error.
+pub(crate) fn arrange<T: PartialEq>(placed: Vec<Placed<T>>) -> Result<Vec<Placed<T>>> {
+    let placed = dedupe(placed)?;
+    let mut by_aspect: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, p) in placed.iter().enumerate() {
+        by_aspect.entry(p.aspect.as_str()).or_default().push(i);
+    }
+
+    // edges[i] holds the snippets that must come after snippet i
+    let mut edges: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); placed.len()];
+    for (i, p) in placed.iter().enumerate() {
+        // an aspect's own snippets keep their declared order
+        if let Some(next) = by_aspect[p.aspect.as_str()]
+            .iter()
+            .find(|&&j| j > i && placed[j].phase == p.phase)
+        {
+            edges[i].insert(*next);
+        }
+
+        let constraints = p
+            .after
+            .iter()
+            .map(|t| (t, true))
+            .chain(p.before.iter().map(|t| (t, false)));
+        for (target, after) in constraints {
+            if *target == p.aspect {
+                continue;
+            }
+            let targets: Vec<usize> = if target == EVERY_ASPECT {
+                (0..placed.len())
+                    .filter(|&j| placed[j].aspect != p.aspect && placed[j].phase == p.phase)
+                    .collect()
+            } else {
+                by_aspect.get(target.as_str()).cloned().unwrap_or_default()
+            };
+            for j in targets {
+                let other = &placed[j];
+                let (first, second) = if after { (j, i) } else { (i, j) };
+                if placed[first].phase < placed[second].phase {
+                    continue;
+                }
+                if placed[first].phase > placed[second].phase {
+                    return Err(Error::ConflictingSnippetOrder {
+                        aspect: p.aspect.clone(),
+                        phase: p.phase,
+                        relation: if after { "after" } else { "before" },
+                        target: other.aspect.clone(),
+                        target_phase: other.phase,
+                    });
+                }
+                edges[first].insert(second);
+            }
+        }
+    }
+
+    let mut incoming = vec![0usize; placed.len()];
+    for targets in &edges {
+        for &t in targets {
+            incoming[t] += 1;
+        }
+    }
+
+    // Kahn's algorithm, always taking the earliest ready snippet of the earliest phase
+    let mut ready: BTreeSet<(Phase, usize)> = (0..placed.len())
+        .filter(|&i| incoming[i] == 0)
+        .map(|i| (placed[i].phase, i))
+        .collect();
+    let mut order = Vec::with_capacity(placed.len());
+    while let Some((phase, i)) = ready.iter().next().cloned() {
+        ready.remove(&(phase, i));
+        order.push(i);
+        for &t in &edges[i] {
+            incoming[t] -= 1;
+            if incoming[t] == 0 {
+                ready.insert((placed[t].phase, t));
+            }
+        }
+    }
+
+    if order.len() < placed.len() {
+        let aspects: BTreeSet<String> = (0..placed.len())
+            .filter(|&i| incoming[i] > 0)
+            .map(|i| placed[i].aspect.clone())
+            .collect();
+        return Err(Error::SnippetOrderCycle(
+            aspects.into_iter().collect::<Vec<_>>().join(", "),
+        ));
+    }
+
+    // snippets ordered only by the order they were given in must come from the same aspect
+    let mut follows: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); placed.len()];
+    for &i in order.iter().rev() {
+        for &t in &edges[i] {
+            let reachable = follows[t].clone();
+            follows[i].insert(t);
+            follows[i].extend(reachable);
+        }
+    }
+    for (n, &i) in order.iter().enumerate() {
+        for &j in &order[n + 1..] {
+            let (a, b) = (&placed[i], &placed[j]);
+            if a.phase == b.phase && a.aspect != b.aspect && !follows[i].contains(&j) {
+                return Err(Error::AmbiguousSnippetOrder {
+                    phase: a.phase,
+                    aspect: a.aspect.clone(),
+                    other: b.aspect.clone(),
+                });
+            }
+        }
+    }
+
+    let mut slots: Vec<Option<Placed<T>>> = placed.into_iter().map(Some).collect();
+    Ok(order.into_iter().filter_map(|i| slots[i].take()).collect())
+}
+
+#[cfg(test)]
+mod phase_should {
+    use super::*;
+
+    fn placed(aspect: &str, phase: Phase, after: &[&str], before: &[&str]) -> Placed<&'static str> {
+        Placed {
+            aspect: aspect.to_string(),
+            key: None,
+            phase,
+            after: after.iter().map(|s| s.to_string()).collect(),
+            before: before.iter().map(|s| s.to_string()).collect(),
+            item: "",
+        }
+    }
+
+    fn aspects<T>(arranged: &[Placed<T>]) -> Vec<&str> {
+        arranged.iter().map(|p| p.aspect.as_str()).collect()
+    }
+
+    #[test]
+    fn order_by_phase_then_constraints() -> Result<()> {
+        let arranged = arrange(vec![
+            placed("App", Phase::AppInstall, &["Fonts"], &[]),
+            placed("Fonts", Phase::AppInstall, &[], &[]),
+            placed("Debian", Phase::Base, &[], &[]),
+            placed("X11", Phase::SystemPackages, &[], &["PulseAudio"]),
+            placed("PulseAudio", Phase::SystemPackages, &[], &[]),
+            placed("Locale", Phase::Locale, &["Missing"], &[]),
+        ])?;
+        assert_eq!(
+            aspects(&arranged),
+            vec!["Debian", "X11", "PulseAudio", "Locale", "Fonts", "App"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_an_aspects_own_order() -> Result<()> {
+        let arranged = arrange(vec![
+            placed("B", Phase::AppInstall, &[], &[]),
+            placed("A", Phase::AppInstall, &["B"], &[]),
+            placed("B", Phase::AppInstall, &[], &[]),
+        ])?;
+        assert_eq!(aspects(&arranged), vec!["B", "B", "A"]);
+
+        let arranged = arrange(vec![
+            placed("Fonts", Phase::AppInstall, &[], &[]),
+            placed("Packages", Phase::AppInstall, &[], &[EVERY_ASPECT]),
+            placed("App", Phase::AppInstall, &["Fonts"], &[]),
+            placed("App", Phase::AppInstall, &[], &[]),
+        ])?;
+        assert_eq!(aspects(&arranged), vec!["Packages", "Fonts", "App", "App"]);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_ambiguous_order() {
+        assert!(matches!(
+            arrange(vec![
+                placed("B", Phase::AppInstall, &[], &[]),
+                placed("A", Phase::AppInstall, &[], &[]),
+            ]),
+            Err(Error::AmbiguousSnippetOrder { .. })
+        ));
+        // the second A snippet is only ordered after B by A's own order
+        assert!(matches!(
+            arrange(vec![
+                placed("A", Phase::AppInstall, &[], &[]),
+                placed("B", Phase::AppInstall, &["A"], &[]),
+                placed("C", Phase::AppInstall, &[], &[]),
+            ]),
+            Err(Error::AmbiguousSnippetOrder { .. })
+        ));
+    }
+
+    #[test]
+    fn reject_cycles_and_contradicted_phases() {
+        assert!(matches!(
+            arrange(vec![
+                placed("A", Phase::AppInstall, &["B"], &[]),
+                placed("B", Phase::AppInstall, &["A"], &[]),
+            ]),
+            Err(Error::SnippetOrderCycle(_))
+        ));
+        assert!(matches!(
+            arrange(vec![
+                placed("Locale", Phase::Locale, &["App"], &[]),
+                placed("App", Phase::AppInstall, &[], &[]),
+            ]),
+            Err(Error::ConflictingSnippetOrder { .. })
+        ));
+    }
+
+    #[test]
+    fn emit_keyed_snippets_once() -> Result<()> {
+        let keyed = |aspect: &str, item: &'static str| Placed {
+            key: Some(String::from("group:audio")),
+            item,
+            ..placed(aspect, Phase::SystemPackages, &[], &[])
+        };
+        let arranged = arrange(vec![
+            keyed("PulseAudio", "adduser"),
+            placed("Locale", Phase::Locale, &[], &[]),
+            keyed("Alsa", "adduser"),
+        ])?;
+        assert_eq!(aspects(&arranged), vec!["PulseAudio", "Locale"]);
+
+        assert!(matches!(
+            arrange(vec![
+                keyed("PulseAudio", "adduser"),
+                keyed("Alsa", "groupmod")
+            ]),
+            Err(Error::ConflictingSnippets { .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::path::Path;
+use std::process::Command;
+
+use tar::Archive;
+
+use super::error::{Error, Result};
+use super::runtime::{BuildOptions, ContainerRuntime};
+
+/// Podman runtime; everything goes through the `podman` CLI so that rootless setups work without
+/// a system service.
+pub struct Podman {}
+
+impl ContainerRuntime for Podman {
+    fn program(&self) -> &str {
+        "podman"
+    }
+
+    fn build(&self, options: &BuildOptions, context: &Path) -> Result<String> {
+        // podman build wants a context directory rather than a tar stream, so unpack the
+        // generated archive first
+        let dir = tempfile::Builder::new()
+            .prefix("dfiles-context-")
+            .tempdir()?;
+        Archive::new(File::open(context)?).unpack(dir.path())?;
+
+        let iidfile = dir.path().join(".dfiles-image-id");
+        let args = build_argv(options, dir.path(), &iidfile);
+
+        log::debug!("{} {}", self.program(), args.join(" "));
+        let status = Command::new(self.program())
+            .args(args)
+            .status()
+            .map_err(|e| Error::FailedToSpawn {
+                program: self.program().to_string(),
+                source: e,
+            })?;
+        if !status.success() {
+            return Err(Error::RuntimeCommandFailed(
+                self.program().to_string(),
+                status,
+            ));
+        }
+        Ok(std::fs::read_to_string(iidfile)?.trim().to_string())
+    }
+
+    /// Rootless podman maps container root to the invoking user, which would leave files created
+    /// in profile directories owned by a subordinate uid. `keep-id` maps the host user into the
+    /// container unchanged while the entrypoint still runs as root to set that user up.
+    fn run_argv(&self, args: Vec<String>) -> Vec<String> {
+        let mut argv: Vec<String> = vec!["run", "--userns=keep-id", "--user", "root"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        argv.extend(args);
+        argv
+    }
+}
+
+/// Arguments of the `podman build` invocation for `options`, building the unpacked context in
+/// `dir` and writing the image ID to `iidfile`.
+fn build_argv(options: &BuildOptions, dir: &Path, iidfile: &Path) -> Vec<String> {
+    let mut args = vec![
+        String::from("build"),
+        String::from("-f"),
+        options.dockerfile.clone(),
+        String::from("--iidfile"),
+        iidfile.to_string_lossy().to_string(),
+    ];
+    for tag in &options.tags {
+        args.push(String::from("-t"));
+        args.push(tag.clone());
+    }
+    if options.no_cache {
+        args.push(String::from("--no-cache"));
+    }
+    if options.pull {
+        args.push(String::from("--pull=always"));
+    }
+    for (key, value) in &options.build_args {
+        args.push(String::from("--build-arg"));
+        args.push(format!("{}={}", key, value));
+    }
+    if options.quiet {
+        args.push(String::from("--quiet"));
+    }
+    for (key, value) in &options.labels {
+        args.push(String::from("--label"));
+        args.push(format!("{}={}", key, value));
+    }
+    args.push(dir.to_string_lossy().to_string());
+    args
+}
+
+#[cfg(test)]
+mod podman_should {
+    use super::*;
+
+    #[test]
+    fn pass_build_options_to_podman() {
+        let options = BuildOptions {
+            dockerfile: String::from("Dockerfile"),
+            tags: vec![String::from("waynr/zoom:1"), String::from("zoom:latest")],
+            no_cache: true,
+            pull: true,
+            build_args: vec![(String::from("CHANNEL"), String::from("beta"))],
+            quiet: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            build_argv(&options, Path::new("/ctx"), Path::new("/ctx/iid")).join(" "),
+            "build -f Dockerfile --iidfile /ctx/iid -t waynr/zoom:1 -t zoom:latest \
+             --no-cache --pull=always --build-arg CHANNEL=beta --quiet /ctx"
+        );
+    }
+}
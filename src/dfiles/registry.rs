@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{Error, Result};
+
+const DOCKER_HUB: &str = "index.docker.io";
+
+/// A registry app images are pushed to and pulled from, optionally with a namespace, e.g.
+/// `localhost:5000` or `ghcr.io/waynr`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Registry(String);
+
+impl Registry {
+    /// Reference of a local image tag such as `waynr/firefox:1` in this registry.
+    pub fn reference(&self, tag: &str) -> String {
+        format!("{}/{}", self.0, tag)
+    }
+}
+
+impl TryFrom<&String> for Registry {
+    type Error = Error;
+    fn try_from(value: &String) -> Result<Self> {
+        let registry = value.trim_end_matches('/');
+        if registry.is_empty()
+            || registry.contains("://")
+            || registry.chars().any(char::is_whitespace)
+        {
+            return Err(Error::InvalidRegistry(value.to_string()));
+        }
+        Ok(Registry(registry.to_string()))
+    }
+}
+
+/// Host of the registry an image reference points at, Docker Hub unless the first path component
+/// names a host.
+pub(crate) fn registry_host(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if host.contains('.') || host.contains(':') || host == "localhost" => host,
+        _ => DOCKER_HUB,
+    }
+}
+
+/// Registry credentials as stored in the standard Docker config.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    pub serveraddress: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+    creds_store: Option<String>,
+    #[serde(default)]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct AuthEntry {
+    auth: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    username: String,
+    secret: String,
+}
+
+/// Path of the Docker CLI config, honouring `DOCKER_CONFIG`.
+fn docker_config_path() -> Option<PathBuf> {
+    match env::var_os("DOCKER_CONFIG") {
+        Some(dir) => Some(PathBuf::from(dir).join("config.json")),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker/config.json")),
+    }
+}
+
+/// Looks up credentials for the registry `host` in the Docker config at `path`, asking the
+/// configured credential helper where there is one.
+pub(crate) fn credentials(path: &Path, host: &str) -> Result<Option<Credentials>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let config: DockerConfig = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|_| Error::FailedToLoadDockerConfig)?;
+    let serveraddress = match host {
+        DOCKER_HUB => String::from("https://index.docker.io/v1/"),
+        _ => host.to_string(),
+    };
+
+    // auths keys may carry a scheme and path, e.g. https://index.docker.io/v1/
+    let normalize = |key: &str| {
+        let key = key.split_once("://").map_or(key, |(_, rest)| rest);
+        key.split('/').next().unwrap_or_default().to_string()
+    };
+    let helper = config
+        .cred_helpers
+        .iter()
+        .find(|(key, _)| normalize(key) == host)
+        .map(|(_, helper)| helper);
+    let auth = config
+        .auths
+        .iter()
+        .find(|(key, _)| normalize(key) == host)
+        .and_then(|(_, entry)| entry.auth.as_ref());
+
+    match (helper, auth, &config.creds_store) {
+        (Some(helper), _, _) | (None, None, Some(helper)) => {
+            credential_helper(helper, &serveraddress)
+        }
+        (None, Some(auth), _) => {
+            let decoded = base64::decode(auth).map_err(|_| Error::FailedToLoadDockerConfig)?;
+            let decoded = String::from_utf8_lossy(&decoded);
+            let (username, password) = decoded
+                .split_once(':')
+                .ok_or(Error::FailedToLoadDockerConfig)?;
+            Ok(Some(Credentials {
+                username: username.to_string(),
+                password: password.to_string(),
+                serveraddress,
+            }))
+        }
+        (None, None, None) => Ok(None),
+    }
+}
+
+/// Asks `docker-credential-<helper>` for the credentials of `serveraddress`.
+fn credential_helper(helper: &str, serveraddress: &str) -> Result<Option<Credentials>> {
+    let program = format!("docker-credential-{}", helper);
+    let mut child = Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::FailedToSpawn {
+            program: program.clone(),
+            source: e,
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(serveraddress.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    // helpers exit non-zero when they have no credentials for the server
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let found: HelperCredentials =
+        serde_json::from_slice(&output.stdout).map_err(|_| Error::FailedToLoadDockerConfig)?;
+    Ok(Some(Credentials {
+        username: found.username,
+        password: found.secret,
+        serveraddress: serveraddress.to_string(),
+    }))
+}
+
+/// Value of the `X-Registry-Auth` header the engine expects when pushing or pulling `image`; an
+/// empty auth config when the Docker config has no credentials for its registry.
+pub(crate) fn auth_header(image: &str) -> Result<String> {
+    let found = match docker_config_path() {
+        Some(path) => credentials(&path, registry_host(image))?,
+        None => None,
+    };
+    let json = match found {
+        Some(credentials) => serde_json::to_vec(&credentials).map_err(std::io::Error::from)?,
+        None => b"{}".to_vec(),
+    };
+    Ok(base64::encode_config(json, base64::URL_SAFE))
+}
+
+#[cfg(test)]
+mod registry_should {
+    use super::*;
+
+    #[test]
+    fn reference_app_images_in_the_registry() -> Result<()> {
+        let registry = Registry::try_from(&String::from("localhost:5000/"))?;
+        assert_eq!(
+            registry.reference("waynr/firefox:1"),
+            "localhost:5000/waynr/firefox:1"
+        );
+        assert!(Registry::try_from(&String::from("https://localhost:5000")).is_err());
+
+        assert_eq!(
+            registry_host("localhost:5000/waynr/firefox:1"),
+            "localhost:5000"
+        );
+        assert_eq!(registry_host("ghcr.io/waynr/firefox:1"), "ghcr.io");
+        assert_eq!(registry_host("waynr/firefox:1"), DOCKER_HUB);
+        Ok(())
+    }
+
+    #[test]
+    fn read_credentials_from_the_docker_config() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config.json");
+        fs::write(
+            &path,
+            format!(
+                r#"{{"auths": {{"https://index.docker.io/v1/": {{"auth": "{}"}}, "localhost:5000": {{}}}}}}"#,
+                base64::encode("waynr:hunter2")
+            ),
+        )?;
+
+        assert_eq!(
+            credentials(&path, DOCKER_HUB)?,
+            Some(Credentials {
+                username: String::from("waynr"),
+                password: String::from("hunter2"),
+                serveraddress: String::from("https://index.docker.io/v1/"),
+            })
+        );
+        assert_eq!(credentials(&path, "localhost:5000")?, None);
+        assert_eq!(
+            credentials(&dir.path().join("missing.json"), DOCKER_HUB)?,
+            None
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,739 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, IsTerminal};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use serde::{Deserialize, Serialize};
+
+use super::docker;
+use super::engine;
+use super::error::{Error, Result};
+use super::podman;
+use super::signals;
+
+/// Options passed through to the container runtime when building an app image.
+#[derive(Debug, Default)]
+pub struct BuildOptions {
+    pub dockerfile: String,
+    pub tags: Vec<String>,
+    /// Build every step from scratch rather than reusing cached layers.
+    pub no_cache: bool,
+    /// Pull the base image even if a copy exists locally.
+    pub pull: bool,
+    /// `KEY=VAL` pairs for `ARG` instructions in the Dockerfile.
+    pub build_args: Vec<(String, String)>,
+    /// Suppress build output; only the built image ID is printed.
+    pub quiet: bool,
+    /// `KEY=VAL` labels to set on the built image.
+    pub labels: Vec<(String, String)>,
+}
+
+/// The subset of `<runtime> image inspect` output that dfiles cares about.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageInfo {
+    pub id: String,
+    #[serde(default)]
+    pub repo_tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub repo_digests: Option<Vec<String>>,
+    #[serde(default)]
+    pub config: Option<ImageConfig>,
+}
+
+impl ImageInfo {
+    /// Returns the value of the given image label, if set.
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.config
+            .as_ref()?
+            .labels
+            .as_ref()?
+            .get(key)
+            .map(String::as_str)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+/// Label identifying the dfiles app an image was built for or a container was started for.
+pub const APP_LABEL: &str = "dfiles.app";
+/// Label identifying the dfiles profile a container was started with.
+pub const PROFILE_LABEL: &str = "dfiles.profile";
+/// Label identifying the user the app runs as inside the container.
+pub const USER_LABEL: &str = "dfiles.user";
+/// Image label recording the hash of the build context an image was built from.
+pub const CONTEXT_HASH_LABEL: &str = "dfiles.context-hash";
+/// Image label naming the dfiles app.
+pub const TITLE_LABEL: &str = "org.opencontainers.image.title";
+/// Image label recording the app crate version.
+pub const VERSION_LABEL: &str = "org.opencontainers.image.version";
+/// Image label naming the base image.
+pub const BASE_NAME_LABEL: &str = "org.opencontainers.image.base.name";
+/// Image label recording the version of the dfiles library the image was generated with.
+pub const DFILES_VERSION_LABEL: &str = "dfiles.version";
+/// Image label listing the names of the aspects active at build time, comma separated.
+pub const ASPECTS_LABEL: &str = "dfiles.aspects";
+/// Image label listing the config values baked into the image as `key=value`, comma separated.
+pub const CONFIG_LABEL: &str = "dfiles.config";
+
+/// Image labels shown by `inspect`, in display order.
+pub const IMAGE_LABELS: [&str; 7] = [
+    TITLE_LABEL,
+    VERSION_LABEL,
+    BASE_NAME_LABEL,
+    DFILES_VERSION_LABEL,
+    ASPECTS_LABEL,
+    CONFIG_LABEL,
+    CONTEXT_HASH_LABEL,
+];
+
+/// A container as reported by the runtime's container listing.
+#[derive(Debug, Clone)]
+pub struct ContainerSummary {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub state: String,
+    pub status: String,
+    pub labels: HashMap<String, String>,
+}
+
+impl ContainerSummary {
+    pub fn is_running(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+/// Container listing entry as emitted by `docker ps --format json` (one object per line),
+/// `podman ps --format json` (an array) and the engine API, which disagree on a few field
+/// types.
+#[derive(Deserialize, Debug)]
+struct ListedContainer {
+    #[serde(alias = "ID", alias = "Id")]
+    id: String,
+    #[serde(rename = "Names")]
+    names: OneOrMany,
+    #[serde(rename = "Image", default)]
+    image: String,
+    #[serde(rename = "State", default)]
+    state: String,
+    #[serde(rename = "Status", default)]
+    status: String,
+    #[serde(rename = "Labels", default)]
+    labels: Labels,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Labels {
+    Map(Option<HashMap<String, String>>),
+    Text(String),
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Labels::Map(None)
+    }
+}
+
+impl From<ListedContainer> for ContainerSummary {
+    fn from(c: ListedContainer) -> Self {
+        let name = match c.names {
+            OneOrMany::One(s) => s,
+            OneOrMany::Many(v) => v.into_iter().next().unwrap_or_default(),
+        };
+        let labels = match c.labels {
+            Labels::Map(m) => m.unwrap_or_default(),
+            Labels::Text(s) => s
+                .split(',')
+                .filter_map(|kv| kv.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        ContainerSummary {
+            id: c.id,
+            name: name.trim_start_matches('/').to_string(),
+            image: c.image,
+            state: c.state.to_lowercase(),
+            status: c.status,
+            labels,
+        }
+    }
+}
+
+/// Parses container listings, accepting either a JSON array or one JSON object per line.
+pub(crate) fn parse_container_list(output: &[u8]) -> Result<Vec<ContainerSummary>> {
+    let text = String::from_utf8_lossy(output);
+    let listed: Vec<ListedContainer> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(&text).map_err(|_| Error::FailedToListContainers)?
+    } else {
+        text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| Error::FailedToListContainers)?
+    };
+    Ok(listed.into_iter().map(ContainerSummary::from).collect())
+}
+
+/// An image as reported by the runtime's image listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageSummary {
+    pub id: String,
+    /// References of the image, e.g. `waynr/firefox:129.0.1`.
+    pub tags: Vec<String>,
+    /// Creation time in seconds since the epoch.
+    pub created: i64,
+}
+
+/// Image listing entry as emitted by `docker images --format json` (one object per tag and
+/// line), `podman images --format json` (an array) and the engine API.
+#[derive(Deserialize, Debug)]
+struct ListedImage {
+    #[serde(alias = "ID", alias = "Id")]
+    id: String,
+    #[serde(rename = "Repository", default)]
+    repository: Option<String>,
+    #[serde(rename = "Tag", default)]
+    tag: Option<String>,
+    #[serde(rename = "Names", alias = "RepoTags", default)]
+    names: Option<Vec<String>>,
+    #[serde(rename = "Created", default)]
+    created: Option<i64>,
+    #[serde(rename = "CreatedAt", default)]
+    created_at: Option<String>,
+}
+
+/// Parses image listings, accepting either a JSON array or one JSON object per line, and merges
+/// entries for the same image.
+pub(crate) fn parse_image_list(output: &[u8]) -> Result<Vec<ImageSummary>> {
+    let text = String::from_utf8_lossy(output);
+    let listed: Vec<ListedImage> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(&text).map_err(|_| Error::FailedToListImages)?
+    } else {
+        text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| Error::FailedToListImages)?
+    };
+
+    let mut images: Vec<ImageSummary> = Vec::new();
+    for image in listed {
+        let created = image
+            .created
+            .or_else(|| image.created_at.as_deref().and_then(parse_created_at))
+            .unwrap_or_default();
+        let mut tags = image.names.unwrap_or_default();
+        if let (Some(repository), Some(tag)) = (&image.repository, &image.tag) {
+            tags.push(format!("{}:{}", repository, tag));
+        }
+        // podman qualifies unqualified names; dfiles refers to images by their short names
+        let tags = tags.iter().filter(|t| !t.contains("<none>")).map(|t| {
+            t.trim_start_matches("localhost/")
+                .trim_start_matches("docker.io/")
+                .to_string()
+        });
+        let id = image.id;
+        match images.iter_mut().find(|i| i.id == id) {
+            Some(existing) => existing.tags.extend(tags),
+            None => images.push(ImageSummary {
+                id,
+                tags: tags.collect(),
+                created,
+            }),
+        }
+    }
+    Ok(images)
+}
+
+/// Parses the `2024-08-10 12:00:00 -0500 CDT` timestamps of `docker images` into seconds since
+/// the epoch.
+fn parse_created_at(value: &str) -> Option<i64> {
+    let mut parts = value.split_whitespace();
+    let numbers =
+        |s: &str, sep: char| -> Option<Vec<i64>> { s.split(sep).map(|n| n.parse().ok()).collect() };
+    let date = numbers(parts.next()?, '-')?;
+    let time = numbers(parts.next()?, ':')?;
+    let (&[year, month, day], &[hour, minute, second]) = (date.as_slice(), time.as_slice()) else {
+        return None;
+    };
+    let offset = match parts.next() {
+        Some(o) if o.len() == 5 => {
+            let sign = if o.starts_with('-') { -1 } else { 1 };
+            sign * (o[1..3].parse::<i64>().ok()? * 3600 + o[3..5].parse::<i64>().ok()? * 60)
+        }
+        _ => 0,
+    };
+
+    // days since the epoch of the proleptic Gregorian date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// Splits an image reference into its repository and tag, which defaults to `latest`.
+pub(crate) fn split_tag(image: &str) -> (&str, &str) {
+    match image.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (image, "latest"),
+    }
+}
+
+/// A container runtime capable of building, running and inspecting dfiles app images.
+pub trait ContainerRuntime {
+    /// Name of the command line program used to drive the runtime.
+    fn program(&self) -> &str;
+
+    /// Builds an image from the tar archive at `context` and returns the ID of the built image.
+    fn build(&self, options: &BuildOptions, context: &Path) -> Result<String>;
+
+    /// Returns the full argument vector passed to the runtime program to run a container with the
+    /// given `run` command line arguments.
+    fn run_argv(&self, args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![String::from("run")];
+        argv.extend(args);
+        argv
+    }
+
+    /// Runs a container using the given `run` command line arguments and returns its exit code.
+    fn run(&self, args: Vec<String>) -> Result<i32> {
+        spawn(self.program(), self.run_argv(args))
+    }
+
+    /// Returns information about the named image or `None` if it does not exist locally.
+    fn inspect_image(&self, image: &str) -> Result<Option<ImageInfo>> {
+        let output = Command::new(self.program())
+            .args(vec!["image", "inspect", image])
+            .output()
+            .map_err(|e| Error::FailedToSpawn {
+                program: self.program().to_string(),
+                source: e,
+            })?;
+
+        if !output.status.success() {
+            if is_missing_image(&output.stderr) {
+                return Ok(None);
+            }
+            log::error!("{}", String::from_utf8_lossy(&output.stderr).trim());
+            return Err(Error::RuntimeCommandFailed(
+                self.program().to_string(),
+                output.status,
+            ));
+        }
+
+        let mut infos: Vec<ImageInfo> =
+            serde_json::from_slice(&output.stdout).map_err(|_| Error::FailedToInspectImage)?;
+        Ok(infos.pop())
+    }
+
+    /// Pulls the named image from its registry.
+    fn pull(&self, image: &str) -> Result<()> {
+        match spawn(
+            self.program(),
+            vec![String::from("pull"), image.to_string()],
+        )? {
+            0 => Ok(()),
+            _ => Err(Error::FailedToPullImage(image.to_string())),
+        }
+    }
+
+    /// Tags the local image `source` as `target`.
+    fn tag(&self, source: &str, target: &str) -> Result<()> {
+        match spawn(
+            self.program(),
+            vec![String::from("tag"), source.to_string(), target.to_string()],
+        )? {
+            0 => Ok(()),
+            _ => Err(Error::FailedToTagImage(
+                source.to_string(),
+                target.to_string(),
+            )),
+        }
+    }
+
+    /// Pushes the named image to its registry.
+    fn push(&self, image: &str) -> Result<()> {
+        match spawn(
+            self.program(),
+            vec![String::from("push"), image.to_string()],
+        )? {
+            0 => Ok(()),
+            _ => Err(Error::FailedToPushImage(image.to_string())),
+        }
+    }
+
+    /// Saves the named images, along with their tags, to a tar archive at `output`.
+    fn save(&self, images: &[String], output: &Path) -> Result<()> {
+        let mut argv = vec![
+            String::from("save"),
+            String::from("--output"),
+            output.display().to_string(),
+        ];
+        argv.extend(images.iter().cloned());
+        match spawn(self.program(), argv)? {
+            0 => Ok(()),
+            _ => Err(Error::FailedToSaveImage(images.join(", "))),
+        }
+    }
+
+    /// Loads the images, along with their tags, from a tar archive written by `save`.
+    fn load(&self, input: &Path) -> Result<()> {
+        match spawn(
+            self.program(),
+            vec![
+                String::from("load"),
+                String::from("--input"),
+                input.display().to_string(),
+            ],
+        )? {
+            0 => Ok(()),
+            _ => Err(Error::FailedToLoadImage(input.display().to_string())),
+        }
+    }
+
+    /// Lists local images, untagged ones included, that match all of the given `key=value`
+    /// filters, e.g. `reference=waynr/firefox` or `label=dfiles.app=firefox`.
+    fn list_images(&self, filters: &[String]) -> Result<Vec<ImageSummary>> {
+        let mut argv: Vec<&str> = vec!["images", "--format", "json"];
+        for filter in filters {
+            argv.push("--filter");
+            argv.push(filter);
+        }
+        let output = Command::new(self.program())
+            .args(argv)
+            .output()
+            .map_err(|e| Error::FailedToSpawn {
+                program: self.program().to_string(),
+                source: e,
+            })?;
+        if !output.status.success() {
+            return Err(Error::RuntimeCommandFailed(
+                self.program().to_string(),
+                output.status,
+            ));
+        }
+        parse_image_list(&output.stdout)
+    }
+
+    /// Removes the given image reference; the image itself is deleted along with its last tag.
+    fn remove_image(&self, image: &str) -> Result<()> {
+        match spawn(self.program(), vec![String::from("rmi"), image.to_string()])? {
+            0 => Ok(()),
+            _ => Err(Error::FailedToRemoveImage(image.to_string())),
+        }
+    }
+
+    /// Lists containers, running or not, that carry all of the given `key=value` labels.
+    fn list_containers(&self, labels: &[String]) -> Result<Vec<ContainerSummary>> {
+        let filters: Vec<String> = labels.iter().map(|l| format!("label={}", l)).collect();
+        let mut argv: Vec<&str> = vec!["ps", "--all", "--format", "json"];
+        for filter in &filters {
+            argv.push("--filter");
+            argv.push(filter);
+        }
+        let output = Command::new(self.program())
+            .args(argv)
+            .output()
+            .map_err(|e| Error::FailedToSpawn {
+                program: self.program().to_string(),
+                source: e,
+            })?;
+        if !output.status.success() {
+            return Err(Error::RuntimeCommandFailed(
+                self.program().to_string(),
+                output.status,
+            ));
+        }
+        parse_container_list(&output.stdout)
+    }
+
+    /// Returns the full argument vector passed to the runtime program to run `cmd` in an already
+    /// running container.
+    fn exec_argv(&self, container: &str, user: Option<&str>, cmd: Vec<String>) -> Vec<String> {
+        let mut argv = vec![String::from("exec")];
+        if io::stdin().is_terminal() {
+            argv.push(String::from("-it"));
+        }
+        if let Some(user) = user {
+            argv.push(String::from("--user"));
+            argv.push(user.to_string());
+        }
+        argv.push(container.to_string());
+        argv.extend(cmd);
+        argv
+    }
+
+    /// Runs `cmd` in an already running container, as `user` if given, and returns its exit code.
+    fn exec(&self, container: &str, user: Option<&str>, cmd: Vec<String>) -> Result<i32> {
+        spawn(self.program(), self.exec_argv(container, user, cmd))
+    }
+
+    /// Prints the logs of the given container, optionally following new output.
+    fn logs(&self, container: &str, follow: bool) -> Result<i32> {
+        let mut argv = vec![String::from("logs")];
+        if follow {
+            argv.push(String::from("--follow"));
+        }
+        argv.push(container.to_string());
+        spawn(self.program(), argv)
+    }
+
+    /// Stops the given container.
+    fn stop(&self, container: &str) -> Result<()> {
+        match spawn(
+            self.program(),
+            vec![String::from("stop"), container.to_string()],
+        )? {
+            0 => Ok(()),
+            _ => Err(Error::FailedToStopContainer(container.to_string())),
+        }
+    }
+
+    /// Attaches the current terminal to the given container and returns its exit code once it
+    /// stops.
+    fn attach(&self, container: &str) -> Result<i32> {
+        spawn(
+            self.program(),
+            vec![String::from("attach"), container.to_string()],
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Runtime {
+    #[default]
+    Docker,
+    Podman,
+    /// Docker without the CLI, talking to the engine socket directly.
+    Engine,
+}
+
+impl Runtime {
+    pub fn connect(&self) -> Result<Box<dyn ContainerRuntime>> {
+        Ok(match self {
+            Runtime::Docker => Box::new(docker::Docker::connect()?),
+            Runtime::Podman => Box::new(podman::Podman {}),
+            Runtime::Engine => Box::new(engine::Engine::connect()?),
+        })
+    }
+}
+
+impl TryFrom<&String> for Runtime {
+    type Error = Error;
+    fn try_from(value: &String) -> Result<Self> {
+        match value.as_str() {
+            "docker" => Ok(Runtime::Docker),
+            "podman" => Ok(Runtime::Podman),
+            "engine" => Ok(Runtime::Engine),
+            _ => Err(Error::InvalidRuntime(value.to_string())),
+        }
+    }
+}
+
+/// Whether the error output of a failed `image inspect` says the image doesn't exist, as opposed
+/// to the runtime being unreachable or otherwise failing.
+fn is_missing_image(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr).to_lowercase();
+    // docker says "no such image" or "no such object", podman "image not known"
+    ["no such image", "no such object", "image not known"]
+        .iter()
+        .any(|m| stderr.contains(m))
+}
+
+/// Runs `<program> <argv>` in the foreground, inheriting stdio from the current process, and
+/// returns its exit code. Quit signals received in the meantime are passed on to the child.
+fn spawn(program: &str, argv: Vec<String>) -> Result<i32> {
+    log::debug!("{}", command_line(program, &argv));
+
+    let mut child = Command::new(program)
+        .args(argv)
+        .spawn()
+        .map_err(|e| Error::FailedToSpawn {
+            program: program.to_string(),
+            source: e,
+        })?;
+
+    let _signals = signals::forward_to_child(child.id())?;
+    let status = child.wait()?;
+    Ok(exit_code(status))
+}
+
+/// Converts an exit status into a shell-style exit code, where termination by a signal is
+/// reported as 128 plus the signal number.
+pub(crate) fn exit_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
+
+/// Renders a program invocation as a string that can be pasted into a POSIX shell.
+pub fn command_line(program: &str, argv: &[String]) -> String {
+    let mut words = vec![shell_quote(program)];
+    words.extend(argv.iter().map(|a| shell_quote(a)));
+    words.join(" ")
+}
+
+/// Quotes a word for a POSIX shell, leaving words that need no quoting as they are.
+pub(crate) fn shell_quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./-_".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r#"'\''"#))
+}
+
+#[cfg(test)]
+mod runtime_should {
+    use super::*;
+
+    #[test]
+    fn convert_from_str() -> Result<()> {
+        assert_eq!(Runtime::try_from(&String::from("docker"))?, Runtime::Docker);
+        assert_eq!(Runtime::try_from(&String::from("podman"))?, Runtime::Podman);
+        assert!(Runtime::try_from(&String::from("lxc")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_container_lists() -> Result<()> {
+        // docker ps --format json
+        let docker = br#"{"ID":"abc","Names":"firefox-work","Image":"waynr/firefox:1","State":"running","Status":"Up 2 minutes","Labels":"dfiles.app=firefox,dfiles.profile=work"}
+{"ID":"def","Names":"firefox-default","Image":"waynr/firefox:1","State":"exited","Status":"Exited (0)","Labels":"dfiles.app=firefox,dfiles.profile=default"}
+"#;
+        let containers = parse_container_list(docker)?;
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].name, "firefox-work");
+        assert!(containers[0].is_running());
+        assert_eq!(containers[1].labels[PROFILE_LABEL], "default");
+
+        // podman ps --format json and the engine API
+        let podman = br#"[{"Id":"abc","Names":["/firefox-work"],"Image":"waynr/firefox:1","State":"running","Labels":{"dfiles.app":"firefox","dfiles.profile":"work"}}]"#;
+        let containers = parse_container_list(podman)?;
+        assert_eq!(containers[0].id, "abc");
+        assert_eq!(containers[0].name, "firefox-work");
+        assert_eq!(containers[0].labels[APP_LABEL], "firefox");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_image_lists() -> Result<()> {
+        // docker images --format json
+        let docker = br#"{"ID":"abc","Repository":"waynr/firefox","Tag":"129.0.1","CreatedAt":"2024-08-10 12:00:00 -0500 CDT"}
+{"ID":"abc","Repository":"localhost:5000/waynr/firefox","Tag":"129.0.1","CreatedAt":"2024-08-10 12:00:00 -0500 CDT"}
+{"ID":"def","Repository":"waynr/firefox","Tag":"<none>","CreatedAt":"2024-07-01 09:30:00 +0000 UTC"}
+"#;
+        let images = parse_image_list(docker)?;
+        assert_eq!(images.len(), 2);
+        assert_eq!(
+            images[0].tags,
+            vec![
+                "waynr/firefox:129.0.1",
+                "localhost:5000/waynr/firefox:129.0.1"
+            ]
+        );
+        assert_eq!(images[0].created, 1723309200);
+        assert!(images[1].tags.is_empty());
+        assert_eq!(images[1].created, 1719826200);
+
+        // podman images --format json and the engine API
+        let podman =
+            br#"[{"Id":"abc","Names":["localhost/waynr/firefox:129.0.1"],"Created":1723309200}]"#;
+        let images = parse_image_list(podman)?;
+        assert_eq!(images[0].tags, vec!["waynr/firefox:129.0.1"]);
+        assert_eq!(images[0].created, 1723309200);
+        Ok(())
+    }
+
+    /// Stands in for a runtime program with a script that fails `image inspect` with `stderr`.
+    struct FailingInspect {
+        _dir: tempfile::TempDir,
+        program: String,
+    }
+
+    impl FailingInspect {
+        fn new(stderr: &str) -> Result<Self> {
+            let dir = tempfile::tempdir()?;
+            let script = dir.path().join("runtime");
+            std::fs::write(
+                &script,
+                format!("#!/bin/sh\necho '{}' >&2\nexit 1\n", stderr),
+            )?;
+            std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+            Ok(FailingInspect {
+                program: script.display().to_string(),
+                _dir: dir,
+            })
+        }
+    }
+
+    impl ContainerRuntime for FailingInspect {
+        fn program(&self) -> &str {
+            &self.program
+        }
+
+        fn build(&self, _: &BuildOptions, _: &Path) -> Result<String> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn report_only_missing_images_as_none() -> Result<()> {
+        for stderr in [
+            "Error response from daemon: No such image: waynr/firefox:1",
+            "Error: No such object: waynr/firefox:1",
+            "Error: waynr/firefox:1: image not known",
+        ] {
+            assert!(FailingInspect::new(stderr)?
+                .inspect_image("waynr/firefox:1")?
+                .is_none());
+        }
+        assert!(matches!(
+            FailingInspect::new("Cannot connect to the Docker daemon")?
+                .inspect_image("waynr/firefox:1"),
+            Err(Error::RuntimeCommandFailed(_, _))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn report_exit_codes() {
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), 0);
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+        // terminated by SIGKILL
+        assert_eq!(exit_code(ExitStatus::from_raw(9)), 137);
+    }
+
+    #[test]
+    fn quote_command_line() {
+        let argv: Vec<String> = vec!["run", "-e", "TZ=America/Chicago", "--name", "", "it's"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            command_line("docker", &argv),
+            r#"docker run -e TZ=America/Chicago --name '' 'it'\''s'"#
+        );
+    }
+}
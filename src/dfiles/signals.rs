@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+use super::error::Result;
+
+/// Signals asking a dfiles app to quit, e.g. from a terminal or a session manager.
+const QUIT_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Write end of the pipe the signal handler reports to, or -1 while no forwarder is active.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn report(signal: libc::c_int) {
+    let fd = PIPE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = signal as u8;
+        unsafe {
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Hands quit signals received by the dfiles process to a callback instead of letting them
+/// terminate it, so that the container can be shut down cleanly and its exit code reported. The
+/// previous signal dispositions are restored when the forwarder is dropped. Only one forwarder
+/// may be active at a time.
+pub(crate) struct Forwarder {
+    write_fd: libc::c_int,
+    previous: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+impl Forwarder {
+    /// Installs handlers for quit signals and starts a thread that passes each one received to
+    /// `forward`. Child processes get the default dispositions back when they exec.
+    pub(crate) fn start<F>(forward: F) -> Result<Forwarder>
+    where
+        F: Fn(libc::c_int) + Send + 'static,
+    {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let [read_fd, write_fd] = fds;
+        PIPE.store(write_fd, Ordering::SeqCst);
+
+        let mut previous = Vec::new();
+        for signal in QUIT_SIGNALS {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = report as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                let mut old: libc::sigaction = std::mem::zeroed();
+                libc::sigaction(signal, &action, &mut old);
+                previous.push((signal, old));
+            }
+        }
+
+        // the reader sees end of file once the forwarder closes the write end
+        let mut reader = unsafe { File::from_raw_fd(read_fd) };
+        thread::spawn(move || {
+            let mut byte = [0u8; 1];
+            while let Ok(1) = reader.read(&mut byte) {
+                log::debug!("forwarding signal {}", byte[0]);
+                forward(byte[0] as libc::c_int);
+            }
+        });
+
+        Ok(Forwarder { write_fd, previous })
+    }
+}
+
+impl Drop for Forwarder {
+    fn drop(&mut self) {
+        unsafe {
+            for (signal, old) in &self.previous {
+                libc::sigaction(*signal, old, std::ptr::null_mut());
+            }
+            PIPE.store(-1, Ordering::SeqCst);
+            libc::close(self.write_fd);
+        }
+    }
+}
+
+/// Forwards quit signals to the child process `pid`. SIGINT is left out because a child in the
+/// foreground process group already receives it from the terminal.
+pub(crate) fn forward_to_child(pid: u32) -> Result<Forwarder> {
+    Forwarder::start(move |signal| {
+        if signal != libc::SIGINT {
+            unsafe {
+                libc::kill(pid as libc::pid_t, signal);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod signals_should {
+    use super::*;
+    use std::process::Command;
+
+    use super::super::runtime::exit_code;
+
+    #[test]
+    fn forward_quit_signals_to_the_child() -> Result<()> {
+        let mut child = Command::new("sleep").arg("30").spawn()?;
+        let forwarder = forward_to_child(child.id())?;
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGTERM);
+        }
+        assert_eq!(exit_code(child.wait()?), 128 + libc::SIGTERM);
+
+        drop(forwarder);
+        let mut restored: libc::sigaction = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::sigaction(libc::SIGTERM, std::ptr::null(), &mut restored);
+        }
+        assert_eq!(restored.sa_sigaction, libc::SIG_DFL);
+        Ok(())
+    }
+}